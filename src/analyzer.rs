@@ -1,173 +1,875 @@
 use serde::{Deserialize, Serialize};
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Range;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use crate::backend::{BackendError, GenerateResponse, LlmBackend};
+use crate::cache::{hash_hex, CacheKey, ResponseCache};
+use crate::difficulty::{self, Difficulty, WordFrequency};
+use crate::grammar::GrammarTaxonomy;
+use crate::language::Language;
 use crate::parser::Subtitle;
+use crate::prompts::{self, PromptTemplate, PromptTemplates, PromptVariables};
+use crate::timestamp::Timestamp;
 
-#[derive(Serialize)]
-struct ApiRequest {
-    model: String,
-    prompt: String,
-    temperature: f32,
-    stream: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    format: Option<String>,
-}
+// 再試行の待ち時間の上限
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
-#[derive(Deserialize)]
-struct ApiResponse {
-    response: String,
-    #[serde(default)]
-    eval_count: Option<u64>,
-    #[serde(default)]
-    eval_duration: Option<u64>,
+// 句読点復元で1回のリクエストに含める最大語数
+const PUNCTUATION_CHUNK_WORDS: usize = 80;
+// 空白で区切らない言語では1文字ずつ扱い、この文字数ごとに問い合わせる
+const PUNCTUATION_CHUNK_CHARS: usize = 160;
+
+pub struct AnalysisOptions {
+    // 先頭から解析する文の数。None なら全て
+    pub limit: Option<usize>,
+    // 並行して解析する文の数
+    pub concurrency: usize,
+    // 既存の analysis.jsonl にある文を解析し直さない
+    pub resume: bool,
+    // resume のとき、失敗した文は解析し直す
+    pub retry_failed: bool,
+    pub retry: RetryPolicy,
+    // None ならキャッシュを使わない
+    pub cache: Option<Arc<ResponseCache>>,
+    // JSONスキーマを指定した1回のリクエストで解析し、失敗したら翻訳と解説を別々に取得する
+    pub structured: bool,
+    pub prompts: Arc<PromptTemplates>,
+    // テンプレートの {title} に入る動画タイトルまたはファイル名
+    pub title: String,
+    // テンプレートの {context} に入れる前後の文の数。0 なら {context} は空
+    pub context: usize,
+    // 文にタグ付けする文法事項の一覧。None ならタグ付けしない（--grammar-tags か --structured のときだけ指定する）
+    pub grammar: Option<Arc<GrammarTaxonomy>>,
+    // 難易度の計算に、LLMによる1〜5の評価も使う
+    pub rate_difficulty: bool,
+    // 翻訳・解説とは別のリクエストで語彙を取得する（--structured の1回のリクエストには常に含まれる）
+    pub vocabulary: bool,
+    // 字幕の言語と、翻訳・解説の言語
+    pub source_language: &'static Language,
+    pub target_language: &'static Language,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct AnalysisResult {
-    pub timestamp: String,
+    #[serde(alias = "timestamp")]
+    pub start: Timestamp,
+    #[serde(default)]
+    pub end: Timestamp,
     pub original_sentence: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
     pub translation: String,
     pub explanation: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vocabulary: Vec<VocabularyItem>,
+    // 構造化出力（--structured）のときだけ入る
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grammar_points: Vec<GrammarPoint>,
+    // 文法事項の一覧（grammar_taxonomy.txt）のうち、この文に当てはまるもののID
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grammar_tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
+    // 解析に使ったプロンプトテンプレート（translation, explanation, vocabulary, structured）ごとのハッシュ
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub template_hashes: BTreeMap<String, String>,
+    // 以前の analysis.jsonl にはないので、読み込み時は ok とみなす（read_analysis_jsonl で旧形式のエラー文字列も判定する）
+    #[serde(default)]
+    pub status: AnalysisStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
-async fn call_ollama_api(
-    client: &reqwest::Client,
-    model_name: &str,
-    prompt: String,
-) -> Result<ApiResponse, String> {
-    let request_body = ApiRequest {
-        model: model_name.to_string(),
-        prompt,
-        temperature: 0.3,
-        stream: false,
-        format: None,
-    };
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VocabularyItem {
+    // 文中の形。以前の analysis.jsonl では word
+    #[serde(alias = "word")]
+    pub surface: String,
+    // 辞書形。以前の analysis.jsonl にはない
+    #[serde(default)]
+    pub lemma: String,
+    // noun, verb, phrase など英語の品詞名
+    #[serde(default)]
+    pub part_of_speech: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cefr: Option<CefrLevel>,
+    // 翻訳・解説の言語での文中の意味。以前の analysis.jsonl では meaning
+    #[serde(alias = "meaning")]
+    pub gloss: String,
+}
 
-    let res = client
-        .post("http://localhost:11434/api/generate")
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+// CEFRのレベル。A1 < A2 < ... < C2 の順に比較できる
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CefrLevel {
+    A1,
+    A2,
+    B1,
+    B2,
+    C1,
+    C2,
+}
 
-    if res.status().is_success() {
-        res.json::<ApiResponse>().await.map_err(|e| e.to_string()) 
-    } else {
-        Err(format!("API request failed with status: {}", res.status()))
+impl CefrLevel {
+    pub const ALL: [CefrLevel; 6] = [CefrLevel::A1, CefrLevel::A2, CefrLevel::B1, CefrLevel::B2, CefrLevel::C1, CefrLevel::C2];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CefrLevel::A1 => "A1",
+            CefrLevel::A2 => "A2",
+            CefrLevel::B1 => "B1",
+            CefrLevel::B2 => "B2",
+            CefrLevel::C1 => "C1",
+            CefrLevel::C2 => "C2",
+        }
     }
 }
 
-pub async fn analyze_sentences_from_json(
-    json_path: &Path,
-    model_name: Option<String>,
-    output_dir: &Path,
-    limit: Option<usize>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(120))
-        .no_proxy()
-        .http1_only()
-        .build()?;
-
-    let json_content = fs::read_to_string(json_path)?;
-    let mut subtitles: Vec<Subtitle> = serde_json::from_str(&json_content)?;
-
-    let total_sentences = if let Some(l) = limit {
-        subtitles.truncate(l);
-        println!("Analyzing first {} sentences.", l);
-        l
-    } else {
-        subtitles.len()
-    };
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GrammarPoint {
+    pub name: String,
+    pub description: String,
+}
 
-    let output_path = output_dir.join("analysis.jsonl");
-    fs::write(&output_path, "")?;
-    let mut output_file = OpenOptions::new().append(true).open(&output_path)?;
+// 構造化出力の応答。structured_analysis_schema と同じ形でなければ受け付けない
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructuredAnalysis {
+    translation: String,
+    explanation: String,
+    vocabulary: Vec<VocabularyItem>,
+    grammar_points: Vec<GrammarPoint>,
+    // タグ付けしないときはスキーマにないので省略される
+    #[serde(default)]
+    grammar_tags: Vec<String>,
+}
 
-    println!("Starting analysis with Ollama (/api/generate). This may take a while...");
+// 難易度の評価のリクエスト（--rate-difficulty）の応答
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DifficultyRatingResponse {
+    difficulty: u8,
+}
 
-    let actual_model_name = model_name.unwrap_or_else(|| "gemma3:12b".to_string());
+// 文法事項のタグだけを取得するリクエスト（--structured でないとき）の応答
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GrammarTagsResponse {
+    grammar_tags: Vec<String>,
+}
 
-    for (index, subtitle) in subtitles.iter().enumerate() {
-        let sentence = &subtitle.text;
-        if sentence.trim().is_empty() {
+// 語彙だけを取得するリクエスト（--structured でないとき）の応答
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VocabularyResponse {
+    vocabulary: Vec<VocabularyItem>,
+}
+
+fn string_object_schema(fields: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": fields.iter().map(|field| (field.to_string(), serde_json::json!({ "type": "string" }))).collect::<serde_json::Map<_, _>>(),
+        "required": fields,
+        "additionalProperties": false,
+    })
+}
+
+fn vocabulary_schema() -> serde_json::Value {
+    let mut item = string_object_schema(&["surface", "lemma", "part_of_speech", "cefr", "gloss"]);
+    let levels: Vec<&str> = CefrLevel::ALL.iter().map(|level| level.as_str()).collect();
+    item["properties"]["cefr"] = serde_json::json!({ "type": "string", "enum": levels });
+    serde_json::json!({ "type": "array", "items": item })
+}
+
+// 一覧のIDだけを選べるようにする
+fn grammar_tags_schema(taxonomy: &GrammarTaxonomy) -> serde_json::Value {
+    serde_json::json!({ "type": "array", "items": { "type": "string", "enum": taxonomy.ids() } })
+}
+
+// タグ付けしないときは grammar_tags の項目を含めない
+fn structured_analysis_schema(taxonomy: Option<&GrammarTaxonomy>) -> serde_json::Value {
+    let string = serde_json::json!({ "type": "string" });
+    let mut schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "translation": string,
+            "explanation": string,
+            "vocabulary": vocabulary_schema(),
+            "grammar_points": { "type": "array", "items": string_object_schema(&["name", "description"]) },
+        },
+        "required": ["translation", "explanation", "vocabulary", "grammar_points"],
+        "additionalProperties": false,
+    });
+    if let Some(taxonomy) = taxonomy {
+        schema["properties"]["grammar_tags"] = grammar_tags_schema(taxonomy);
+        schema["required"].as_array_mut().unwrap().push("grammar_tags".into());
+    }
+    schema
+}
+
+fn grammar_tags_response_schema(taxonomy: &GrammarTaxonomy) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": { "grammar_tags": grammar_tags_schema(taxonomy) },
+        "required": ["grammar_tags"],
+        "additionalProperties": false,
+    })
+}
+
+fn difficulty_rating_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": { "difficulty": { "type": "integer", "minimum": 1, "maximum": 5 } },
+        "required": ["difficulty"],
+        "additionalProperties": false,
+    })
+}
+
+fn vocabulary_response_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": { "vocabulary": vocabulary_schema() },
+        "required": ["vocabulary"],
+        "additionalProperties": false,
+    })
+}
+
+// 空の語を拒み、辞書形がなければ文中の形で補う
+fn validate_vocabulary(vocabulary: &mut [VocabularyItem]) -> Result<(), String> {
+    for item in vocabulary.iter_mut() {
+        if item.surface.trim().is_empty() {
+            return Err("empty vocabulary word".to_string());
+        }
+        if item.lemma.trim().is_empty() {
+            item.lemma = item.surface.clone();
+        }
+    }
+    Ok(())
+}
+
+// スキーマで制約していてもモデルやサーバーによっては守られないので、型と必須項目を確かめる
+fn parse_structured_analysis(text: &str) -> Result<StructuredAnalysis, String> {
+    let mut analysis: StructuredAnalysis = serde_json::from_str(text.trim()).map_err(|e| format!("invalid structured response: {}", e))?;
+    if analysis.translation.trim().is_empty() || analysis.explanation.trim().is_empty() {
+        return Err("invalid structured response: empty translation or explanation".to_string());
+    }
+    validate_vocabulary(&mut analysis.vocabulary).map_err(|e| format!("invalid structured response: {}", e))?;
+    if analysis.grammar_points.iter().any(|point| point.name.trim().is_empty()) {
+        return Err("invalid structured response: empty grammar point name".to_string());
+    }
+    Ok(analysis)
+}
+
+fn parse_difficulty_rating(text: &str) -> Result<u8, String> {
+    let response: DifficultyRatingResponse = serde_json::from_str(text.trim()).map_err(|e| format!("invalid difficulty response: {}", e))?;
+    if !(1..=5).contains(&response.difficulty) {
+        return Err(format!("invalid difficulty response: {} is not between 1 and 5", response.difficulty));
+    }
+    Ok(response.difficulty)
+}
+
+fn parse_grammar_tags_response(text: &str) -> Result<Vec<String>, String> {
+    let response: GrammarTagsResponse = serde_json::from_str(text.trim()).map_err(|e| format!("invalid grammar tags response: {}", e))?;
+    Ok(response.grammar_tags)
+}
+
+// 一覧にないIDは捨て、重複を除く。スキーマの enum を守らないモデルもあるため
+fn known_grammar_tags(tags: Vec<String>, taxonomy: &GrammarTaxonomy, label: &str) -> Vec<String> {
+    let mut known: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if taxonomy.label(tag).is_some() {
+            if !known.iter().any(|known_tag| known_tag == tag) {
+                known.push(tag.to_string());
+            }
+        } else {
+            eprintln!("  [{}] Ignoring unknown grammar tag '{}'", label, tag);
+        }
+    }
+    known
+}
+
+fn parse_vocabulary_response(text: &str) -> Result<Vec<VocabularyItem>, String> {
+    let mut response: VocabularyResponse = serde_json::from_str(text.trim()).map_err(|e| format!("invalid vocabulary response: {}", e))?;
+    validate_vocabulary(&mut response.vocabulary).map_err(|e| format!("invalid vocabulary response: {}", e))?;
+    Ok(response.vocabulary)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalysisStatus {
+    #[default]
+    Ok,
+    // 翻訳・解説・難易度の評価の少なくとも1つを取得できなかった。error に理由が入る
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // 一時的なエラーのときに再試行する回数
+    pub max_retries: u32,
+    // 最初の再試行までの待ち時間。再試行のたびに倍になる（最大 MAX_RETRY_DELAY）
+    pub initial_delay: Duration,
+}
+
+// analysis.jsonl を読み込む。途中で中断した実行の書きかけの行など、読めない行は飛ばす
+pub fn read_analysis_jsonl(path: &Path) -> io::Result<Vec<AnalysisResult>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut results = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
             continue;
         }
+        if let Ok(mut result) = serde_json::from_str::<AnalysisResult>(&line) {
+            // 以前はエラーを本文の代わりに書き込んでいた
+            if result.status == AnalysisStatus::Ok
+                && (result.translation.starts_with("Error: Failed to get translation.") || result.explanation.starts_with("Error: Failed to get explanation."))
+            {
+                result.status = AnalysisStatus::Failed;
+                result.error = Some(format!("{} {}", result.translation, result.explanation).trim().to_string());
+            }
+            results.push(result);
+        }
+    }
+    Ok(results)
+}
 
-        println!("Analyzing sentence {}/{}...: \"{}\"", index + 1, total_sentences, &sentence);
+fn write_analysis_jsonl(path: &Path, results: &[AnalysisResult]) -> io::Result<()> {
+    let mut content = String::new();
+    for result in results {
+        content.push_str(&serde_json::to_string(result)?);
+        content.push('\n');
+    }
+    // 書き換え中に中断しても既存の結果を失わないよう、一時ファイルに書いてから置き換える
+    let temp_path = path.with_extension("jsonl.tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, path)
+}
 
-        // 1. Get translation
-        let start_time = Instant::now();
-        let translation_prompt = format!(
-            "あなたは優秀な翻訳家です。以下の英文を自然な日本語に翻訳してください。翻訳文のみを返してください。他の言葉は一切含めないでください。翻訳を\"「\"や\"」\"で囲む必要はありません。\n\nSentence: \"{}\"",
-            sentence
-        );
-        
-        let translation = match call_ollama_api(&client, &actual_model_name, translation_prompt).await {
-            Ok(api_response) => {
-                let elapsed_time = start_time.elapsed();
-                print!("  Translation: Response time: {:.2?}", elapsed_time);
-                if let (Some(eval_count), Some(eval_duration)) = (api_response.eval_count, api_response.eval_duration) {
-                    if eval_duration > 0 {
-                        let tokens_per_second = (eval_count as f64 / eval_duration as f64) * 1_000_000_000.0;
-                        println!(", Tokens/s: {:.2}", tokens_per_second);
-                    } else {
-                        println!("");
-                    }
-                } else {
-                    println!("");
+// 句読点と大文字小文字の違いを無視して単語を比較する
+fn normalize_word(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+// 空白で区切らない言語の応答を元の文字に対応付ける。元の文字以外は句読点だけを許し、直前の文字に付ける
+fn align_punctuated_chars(response: &str, original: &[&str]) -> Option<Vec<String>> {
+    let mut restored: Vec<String> = Vec::with_capacity(original.len());
+    let mut leading = String::new();
+    for c in response.trim().trim_matches(|c| matches!(c, '"' | '「' | '」')).chars().filter(|c| !c.is_whitespace()) {
+        let next = original.get(restored.len()).and_then(|unit| unit.chars().next());
+        if next.is_some_and(|expected| c.to_lowercase().eq(expected.to_lowercase())) {
+            restored.push(std::mem::take(&mut leading) + &c.to_string());
+        } else if c.is_alphanumeric() {
+            return None;
+        } else if let Some(last) = restored.last_mut() {
+            last.push(c);
+        } else {
+            leading.push(c);
+        }
+    }
+    (restored.len() == original.len()).then_some(restored)
+}
+
+// 句読点のない字幕にLLMで句読点と大文字小文字を補う（テンプレートは punctuation）。
+// 単語（空白で区切らない言語では文字）の並びが変わった応答は使わずに元のテキストを残すため、各単語は元のキュー（タイムスタンプ）に留まる。
+pub async fn restore_punctuation(
+    cues: &mut [Subtitle],
+    client: &LlmClient,
+    prompts: &PromptTemplates,
+    source_language: &Language,
+    target_language: &Language,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let without_spaces = source_language.without_spaces;
+    let (chunk_size, separator) = if without_spaces { (PUNCTUATION_CHUNK_CHARS, "") } else { (PUNCTUATION_CHUNK_WORDS, " ") };
+    let words: Vec<(usize, &str)> = cues
+        .iter()
+        .enumerate()
+        .flat_map(|(cue_index, cue)| {
+            let units: Vec<&str> = if without_spaces {
+                cue.text.char_indices().filter(|(_, c)| !c.is_whitespace()).map(|(i, c)| &cue.text[i..i + c.len_utf8()]).collect()
+            } else {
+                cue.text.split_whitespace().collect()
+            };
+            units.into_iter().map(move |word| (cue_index, word))
+        })
+        .collect();
+    let mut restored: Vec<String> = words.iter().map(|(_, word)| word.to_string()).collect();
+
+    let chunk_count = words.len().div_ceil(chunk_size);
+    println!("Restoring punctuation with {} ({}) in {} chunks...", client.backend.name(), client.backend.model(), chunk_count);
+
+    let request = PromptRequest { kind: "Punctuation", template: &prompts.punctuation, schema: None };
+    for (chunk_index, chunk_start) in (0..words.len()).step_by(chunk_size).enumerate() {
+        let chunk_end = (chunk_start + chunk_size).min(words.len());
+        let original: Vec<&str> = words[chunk_start..chunk_end].iter().map(|(_, word)| *word).collect();
+        let text = original.join(separator);
+        let variables = PromptVariables {
+            sentence: &text,
+            previous: "",
+            next: "",
+            title: "",
+            context: "",
+            grammar_tags: "",
+            grammar_tags_field: "",
+            source_language: source_language.name,
+            target_language: target_language.name,
+        };
+        let label = format!("{}/{}", chunk_index + 1, chunk_count);
+
+        // 単語が変わった応答はキャッシュにも保存しない
+        let parse = |response: &str| -> Result<Vec<String>, String> {
+            if without_spaces {
+                return align_punctuated_chars(response, &original).ok_or_else(|| "the model changed the words".to_string());
+            }
+            let tokens: Vec<&str> = response.trim().trim_matches('"').split_whitespace().collect();
+            let same_words = tokens.len() == original.len()
+                && tokens.iter().zip(&original).all(|(token, word)| normalize_word(token) == normalize_word(word));
+            if same_words {
+                Ok(tokens.into_iter().map(str::to_string).collect())
+            } else {
+                Err("the model changed the words".to_string())
+            }
+        };
+        match client.generate_cached(&request, &variables, &label, parse).await {
+            Ok(tokens) => {
+                for (slot, token) in restored[chunk_start..chunk_end].iter_mut().zip(tokens) {
+                    *slot = token;
                 }
-                api_response.response.trim().to_string()
+                println!("  Chunk {} restored.", label);
             }
             Err(e) => {
-                eprintln!("\nError getting translation for sentence '{}': {}", sentence, e);
-                "Error: Failed to get translation.".to_string()
+                eprintln!("  Chunk {}: {}; keeping it unpunctuated.", label, e);
             }
-        };
+        }
+    }
 
-        // 2. Get explanation
+    let mut cue_words: Vec<Vec<String>> = vec![Vec::new(); cues.len()];
+    for ((cue_index, _), word) in words.iter().zip(restored) {
+        cue_words[*cue_index].push(word);
+    }
+    for (cue, words) in cues.iter_mut().zip(cue_words) {
+        if !words.is_empty() {
+            cue.text = words.join(separator);
+        }
+    }
+
+    Ok(())
+}
+
+// 1種類のリクエスト。schema があればJSONスキーマで出力を制約する
+struct PromptRequest<'a> {
+    kind: &'a str,
+    template: &'a PromptTemplate,
+    schema: Option<&'a serde_json::Value>,
+}
+
+impl PromptRequest<'_> {
+    // テンプレート・スキーマ・文以外の変数の値のどれかが変われば別のキャッシュエントリになる
+    fn cache_hash(&self, variables: &PromptVariables) -> String {
+        let mut parts = vec![self.template.hash().to_string()];
+        if let Some(schema) = self.schema {
+            parts.push(schema.to_string());
+        }
+        let context_values = self.template.context_values(variables);
+        // 文以外の変数を使わないか、すべて空（--context 0 の {context} など）なら、プロンプトはテンプレートと文だけで決まる
+        if !context_values.iter().all(|value| value.is_empty()) {
+            parts.extend(context_values.into_iter().map(str::to_string));
+        }
+        if parts.len() == 1 { parts.remove(0) } else { hash_hex(&parts.join("\0")) }
+    }
+}
+
+// バックエンドへの問い合わせに再試行とキャッシュを加える。文の解析と句読点の復元で使う
+pub struct LlmClient {
+    backend: Arc<dyn LlmBackend>,
+    // None ならキャッシュを使わない
+    cache: Option<Arc<ResponseCache>>,
+    retry: RetryPolicy,
+}
+
+impl LlmClient {
+    pub fn new(backend: Arc<dyn LlmBackend>, cache: Option<Arc<ResponseCache>>, retry: RetryPolicy) -> Self {
+        LlmClient { backend, cache, retry }
+    }
+
+    // 一時的なエラーのときは待ち時間を倍にしながら再試行する
+    async fn generate_with_retry(&self, request: &PromptRequest<'_>, prompt: &str, label: &str) -> Result<GenerateResponse, BackendError> {
+        let mut delay = self.retry.initial_delay;
+        let mut attempt = 0;
+        loop {
+            let response = match request.schema {
+                Some(schema) => self.backend.generate_json(prompt, schema).await,
+                None => self.backend.generate(prompt).await,
+            };
+            match response {
+                Err(e) if e.transient && attempt < self.retry.max_retries => {
+                    attempt += 1;
+                    eprintln!("  [{}] {}: {} (retry {}/{} in {:.1?})", label, request.kind, e, attempt, self.retry.max_retries, delay);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // リクエストを送り、応答時間と生成速度を表示する
+    async fn generate_timed(&self, request: &PromptRequest<'_>, prompt: &str, label: &str) -> Result<String, BackendError> {
         let start_time = Instant::now();
-        let explanation_prompt = format!(
-            "あなたは優秀な英文法学者です。以下の英文について、文法的な解説を日本語で提供してください。具体的には、文構造、イディオム・表現（あれば）、英単語（中級以上があれば）についてまとめてください。解説はマークダウン形式で記述してください。解説文のみを返してください。他の言葉は一切含めないでください。最初の横線も不要です。\n\nSentence: \"{}\"",
-            sentence
-        );
-        
-        let explanation = match call_ollama_api(&client, &actual_model_name, explanation_prompt).await {
-            Ok(api_response) => {
-                let elapsed_time = start_time.elapsed();
-                print!("  Explanation: Response time: {:.2?}", elapsed_time);
-                if let (Some(eval_count), Some(eval_duration)) = (api_response.eval_count, api_response.eval_duration) {
-                    if eval_duration > 0 {
-                        let tokens_per_second = (eval_count as f64 / eval_duration as f64) * 1_000_000_000.0;
-                        println!(", Tokens/s: {:.2}", tokens_per_second);
-                    } else {
-                        println!("");
+        let api_response = self.generate_with_retry(request, prompt, label).await?;
+        let elapsed_time = start_time.elapsed();
+        let tokens_per_second = match (api_response.eval_count, api_response.eval_duration) {
+            (Some(eval_count), Some(eval_duration)) if eval_duration > 0 => {
+                format!(", Tokens/s: {:.2}", (eval_count as f64 / eval_duration as f64) * 1_000_000_000.0)
+            }
+            _ => String::new(),
+        };
+        println!("  [{}] {}: Response time: {:.2?}{}", label, request.kind, elapsed_time, tokens_per_second);
+        Ok(api_response.text)
+    }
+
+    // キャッシュにあればそれを返し、なければモデルに問い合わせる。parse に通った応答だけをキャッシュに保存する
+    async fn generate_cached<T>(
+        &self,
+        request: &PromptRequest<'_>,
+        variables: &PromptVariables<'_>,
+        label: &str,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, BackendError> {
+        let template_hash = request.cache_hash(variables);
+        let key = CacheKey {
+            backend: self.backend.name(),
+            model: self.backend.model(),
+            template_hash: &template_hash,
+            sentence: variables.sentence,
+        };
+        if let Some(value) = self.cache.as_ref().and_then(|cache| cache.get(&key)).and_then(|text| parse(&text).ok()) {
+            println!("  [{}] {}: cached", label, request.kind);
+            return Ok(value);
+        }
+
+        let prompt = request.template.render(variables);
+        let text = self.generate_timed(request, &prompt, label).await?;
+        let value = parse(&text).map_err(BackendError::permanent)?;
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.put(&key, &text)
+        {
+            eprintln!("Warning: failed to write to the cache in {}: {}", cache.dir().display(), e);
+        }
+        Ok(value)
+    }
+}
+
+// 各文の解析タスクで共有する設定
+struct SentenceAnalyzer {
+    client: LlmClient,
+    structured: bool,
+    prompts: Arc<PromptTemplates>,
+    title: String,
+    context: usize,
+    grammar: Option<Arc<GrammarTaxonomy>>,
+    // grammar の GrammarTaxonomy::prompt_list と、それを含む構造化出力の grammar_tags の項目。文ごとに作り直さないよう持っておく
+    grammar_list: String,
+    grammar_tags_field: String,
+    rate_difficulty: bool,
+    vocabulary: bool,
+    frequency: Option<WordFrequency>,
+    source_language: &'static Language,
+    target_language: &'static Language,
+    subtitles: Arc<[Subtitle]>,
+}
+
+impl SentenceAnalyzer {
+    // 翻訳・解説・語彙・文法事項を1回のリクエストでまとめて取得する
+    async fn analyze_structured(&self, variables: &PromptVariables<'_>, label: &str) -> Result<StructuredAnalysis, BackendError> {
+        let schema = structured_analysis_schema(self.grammar.as_deref());
+        let request = PromptRequest { kind: "Analysis", template: &self.prompts.structured, schema: Some(&schema) };
+        self.client.generate_cached(&request, variables, label, parse_structured_analysis).await
+    }
+
+    // 文の長さ・語の頻度・文法事項のタグから難易度を計算する。rate_difficulty ならLLMの評価も使う
+    // （語彙やタグと同じく、評価を取得できなくても文を失敗にはせず、評価なしで計算する）。
+    // tagged でなければ（タグ付けしないか、タグを取得できなかったら）、また一覧に節を作る文法事項がなければ節の数は使わない
+    async fn add_difficulty(&self, result: &mut AnalysisResult, variables: &PromptVariables<'_>, label: &str, tagged: bool) {
+        let mut rating = None;
+        if self.rate_difficulty {
+            let schema = difficulty_rating_schema();
+            let request = PromptRequest { kind: "Difficulty", template: &self.prompts.difficulty, schema: Some(&schema) };
+            match self.client.generate_cached(&request, variables, label, parse_difficulty_rating).await {
+                Ok(value) => {
+                    rating = Some(value);
+                    result.template_hashes.insert("difficulty".to_string(), self.prompts.difficulty.hash().to_string());
+                }
+                Err(e) => eprintln!("Warning: failed to get difficulty rating for sentence '{}': {}", variables.sentence, e),
+            }
+        }
+        let counts_clauses = tagged && self.grammar.as_deref().is_some_and(difficulty::has_clause_tags);
+        let grammar_tags = counts_clauses.then_some(result.grammar_tags.as_slice());
+        result.difficulty = Some(difficulty::score_sentence(variables.sentence, self.source_language, self.frequency.as_ref(), grammar_tags, rating));
+    }
+
+    async fn analyze(&self, index: usize, label: String) -> AnalysisResult {
+        let subtitle = &self.subtitles[index];
+        let sentence = &subtitle.text;
+        let text_at = |i: Option<usize>| i.and_then(|i| self.subtitles.get(i)).map_or("", |s| s.text.as_str());
+        let context = if self.context == 0 {
+            String::new()
+        } else {
+            let texts = |range: Range<usize>| -> Vec<&str> { self.subtitles[range].iter().map(|s| s.text.as_str()).collect() };
+            let previous = texts(index.saturating_sub(self.context)..index);
+            let next = texts(index + 1..(index + 1 + self.context).min(self.subtitles.len()));
+            prompts::context_block(&self.title, &previous, &next, self.source_language, self.target_language)
+        };
+        let variables = PromptVariables {
+            sentence,
+            previous: text_at(index.checked_sub(1)),
+            next: text_at(Some(index + 1)),
+            title: &self.title,
+            context: &context,
+            grammar_tags: &self.grammar_list,
+            grammar_tags_field: &self.grammar_tags_field,
+            source_language: self.source_language.name,
+            target_language: self.target_language.name,
+        };
+        println!("Analyzing sentence {}...: \"{}\"", label, sentence);
+        let mut result = AnalysisResult {
+            start: subtitle.start,
+            end: subtitle.end,
+            original_sentence: subtitle.text.clone(),
+            speaker: subtitle.speaker.clone(),
+            translation: String::new(),
+            explanation: String::new(),
+            vocabulary: Vec::new(),
+            grammar_points: Vec::new(),
+            grammar_tags: Vec::new(),
+            difficulty: None,
+            template_hashes: BTreeMap::new(),
+            status: AnalysisStatus::Ok,
+            error: None,
+        };
+
+        if self.structured {
+            match self.analyze_structured(&variables, &label).await {
+                Ok(analysis) => {
+                    result.template_hashes.insert("structured".to_string(), self.prompts.structured.hash().to_string());
+                    result.translation = analysis.translation.trim().to_string();
+                    result.explanation = analysis.explanation;
+                    result.vocabulary = analysis.vocabulary;
+                    result.grammar_points = analysis.grammar_points;
+                    if let Some(grammar) = &self.grammar {
+                        result.grammar_tags = known_grammar_tags(analysis.grammar_tags, grammar, &label);
+                        result.template_hashes.insert("grammar_taxonomy".to_string(), grammar.hash().to_string());
                     }
-                } else {
-                    println!("");
+                    self.add_difficulty(&mut result, &variables, &label, self.grammar.is_some()).await;
+                    return result;
                 }
-                api_response.response
+                Err(e) => eprintln!("  [{}] Structured analysis failed ({}); falling back to separate requests.", label, e),
+            }
+        }
+
+        let mut errors = Vec::new();
+
+        // 1. Get translation
+        let request = PromptRequest { kind: "Translation", template: &self.prompts.translation, schema: None };
+        match self.client.generate_cached(&request, &variables, &label, |text| Ok(text.trim().to_string())).await {
+            Ok(text) => result.translation = text,
+            Err(e) => {
+                eprintln!("Error getting translation for sentence '{}': {}", sentence, e);
+                errors.push(format!("translation: {}", e));
             }
+        }
+
+        // 2. Get explanation
+        let request = PromptRequest { kind: "Explanation", template: &self.prompts.explanation, schema: None };
+        match self.client.generate_cached(&request, &variables, &label, |text| Ok(text.to_string())).await {
+            Ok(text) => result.explanation = text,
             Err(e) => {
-                eprintln!("\nError getting explanation for sentence '{}': {}", sentence, e);
-                format!("Error: Failed to get explanation. Details: {}", e)
+                eprintln!("Error getting explanation for sentence '{}': {}", sentence, e);
+                errors.push(format!("explanation: {}", e));
             }
-        };
+        }
+
+        result.template_hashes.insert("translation".to_string(), self.prompts.translation.hash().to_string());
+        result.template_hashes.insert("explanation".to_string(), self.prompts.explanation.hash().to_string());
+
+        // 3. Get vocabulary（取得できなくても翻訳と解説は使えるので、文を失敗にはしない）
+        if self.vocabulary {
+            let schema = vocabulary_response_schema();
+            let request = PromptRequest { kind: "Vocabulary", template: &self.prompts.vocabulary, schema: Some(&schema) };
+            match self.client.generate_cached(&request, &variables, &label, parse_vocabulary_response).await {
+                Ok(vocabulary) => {
+                    result.vocabulary = vocabulary;
+                    result.template_hashes.insert("vocabulary".to_string(), self.prompts.vocabulary.hash().to_string());
+                }
+                Err(e) => eprintln!("Warning: failed to get vocabulary for sentence '{}': {}", sentence, e),
+            }
+        }
 
-        let result = AnalysisResult {
-            timestamp: subtitle.timestamp.clone(),
-            original_sentence: sentence.clone(),
-            translation,
-            explanation,
+        // 4. Get grammar tags（語彙と同じく、取得できなくても文を失敗にはしない）
+        let mut tagged = false;
+        if let Some(grammar) = &self.grammar {
+            let schema = grammar_tags_response_schema(grammar);
+            let request = PromptRequest { kind: "Grammar tags", template: &self.prompts.grammar_tags, schema: Some(&schema) };
+            match self.client.generate_cached(&request, &variables, &label, parse_grammar_tags_response).await {
+                Ok(tags) => {
+                    result.grammar_tags = known_grammar_tags(tags, grammar, &label);
+                    tagged = true;
+                    result.template_hashes.insert("grammar_tags".to_string(), self.prompts.grammar_tags.hash().to_string());
+                    result.template_hashes.insert("grammar_taxonomy".to_string(), grammar.hash().to_string());
+                }
+                Err(e) => eprintln!("Warning: failed to get grammar tags for sentence '{}': {}", sentence, e),
+            }
+        }
+        self.add_difficulty(&mut result, &variables, &label, tagged).await;
+
+        if !errors.is_empty() {
+            result.status = AnalysisStatus::Failed;
+            result.error = Some(errors.join("; "));
+        }
+        result
+    }
+}
+
+// 最大 concurrency 文を並行して解析する。結果は完了順ではなく元の文の順に analysis.jsonl へ書き出す。
+// resume なら既存の analysis.jsonl にある文（開始時刻と本文が一致するもの）は解析し直さない。retry_failed なら失敗した文だけは解析し直す。
+// limit は解析する文を選ぶだけで、範囲外の文やどの文とも一致しない既存の結果はそのまま残す
+pub async fn analyze_sentences(
+    subtitles: &[Subtitle],
+    backend: Arc<dyn LlmBackend>,
+    output_dir: &Path,
+    options: &AnalysisOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // 前後の文は --limit の範囲外からも取る
+    let all_subtitles = subtitles;
+    let subtitles = match options.limit {
+        Some(l) if l < subtitles.len() => {
+            println!("Analyzing first {} sentences.", l);
+            &subtitles[..l]
+        }
+        _ => subtitles,
+    };
+    let total_sentences = subtitles.len();
+
+    let output_path = output_dir.join("analysis.jsonl");
+    let mut completed: HashMap<(Timestamp, String), AnalysisResult> = HashMap::new();
+    if options.resume {
+        match read_analysis_jsonl(&output_path) {
+            Ok(results) => {
+                completed = results.into_iter().map(|result| ((result.start, result.original_sentence.clone()), result)).collect();
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    // 解析済みの文を元の順に並べ直して書き出し、残りの文をその後ろに追記していく
+    let mut done: Vec<AnalysisResult> = Vec::new();
+    let mut remaining: Vec<usize> = Vec::new();
+    for (index, subtitle) in all_subtitles.iter().enumerate() {
+        let Some(result) = completed.remove(&(subtitle.start, subtitle.text.clone())) else {
+            if index < total_sentences && !subtitle.text.trim().is_empty() {
+                remaining.push(index);
+            }
+            continue;
         };
+        if index < total_sentences && options.retry_failed && result.status == AnalysisStatus::Failed {
+            remaining.push(index);
+        } else {
+            done.push(result);
+        }
+    }
+    let mut unmatched: Vec<AnalysisResult> = completed.into_values().collect();
+    unmatched.sort_by(|a, b| (a.start, &a.original_sentence).cmp(&(b.start, &b.original_sentence)));
+    done.extend(unmatched);
+    write_analysis_jsonl(&output_path, &done)?;
+    if options.resume {
+        println!("Resuming: {} sentences already analyzed, {} remaining.", done.len(), remaining.len());
+    }
+    let mut output_file = OpenOptions::new().append(true).open(&output_path)?;
 
-        let json_line = serde_json::to_string(&result)?;
-        writeln!(output_file, "{}", json_line)?;
+    let concurrency = options.concurrency.max(1);
+    if concurrency > 1 {
+        println!("Starting analysis with {} ({}), {} sentences in parallel. This may take a while...", backend.name(), backend.model(), concurrency);
+    } else {
+        println!("Starting analysis with {} ({}). This may take a while...", backend.name(), backend.model());
+    }
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut pending: VecDeque<JoinHandle<AnalysisResult>> = VecDeque::new();
+    let mut failed = done.iter().filter(|result| result.status == AnalysisStatus::Failed).count();
+
+    let analyzer = Arc::new(SentenceAnalyzer {
+        client: LlmClient::new(backend.clone(), options.cache.clone(), options.retry),
+        structured: options.structured,
+        prompts: options.prompts.clone(),
+        title: options.title.clone(),
+        context: options.context,
+        grammar: options.grammar.clone(),
+        grammar_list: options.grammar.as_ref().map(|grammar| grammar.prompt_list()).unwrap_or_default(),
+        grammar_tags_field: options
+            .grammar
+            .as_ref()
+            .map(|grammar| prompts::grammar_tags_field(&grammar.prompt_list(), options.source_language, options.target_language))
+            .unwrap_or_default(),
+        rate_difficulty: options.rate_difficulty,
+        vocabulary: options.vocabulary,
+        frequency: WordFrequency::for_language(options.source_language),
+        source_language: options.source_language,
+        target_language: options.target_language,
+        subtitles: all_subtitles.into(),
+    });
+
+    for index in remaining {
+        let permit = semaphore.clone().acquire_owned().await?;
+        let analyzer = analyzer.clone();
+        let label = format!("{}/{}", index + 1, total_sentences);
+        pending.push_back(tokio::spawn(async move {
+            let result = analyzer.analyze(index, label).await;
+            drop(permit);
+            result
+        }));
+
+        // 先頭から順に、完了しているものだけ書き出す
+        while pending.front().is_some_and(|handle| handle.is_finished()) {
+            let result = pending.pop_front().unwrap().await?;
+            failed += usize::from(result.status == AnalysisStatus::Failed);
+            writeln!(output_file, "{}", serde_json::to_string(&result)?)?;
+        }
+    }
+
+    for handle in pending {
+        let result = handle.await?;
+        failed += usize::from(result.status == AnalysisStatus::Failed);
+        writeln!(output_file, "{}", serde_json::to_string(&result)?)?;
+    }
+
+    if !done.is_empty() {
+        let mut results = read_analysis_jsonl(&output_path)?;
+        let order: HashMap<(Timestamp, &str), usize> = all_subtitles
+            .iter()
+            .enumerate()
+            .map(|(index, subtitle)| ((subtitle.start, subtitle.text.as_str()), index))
+            .collect();
+        results.sort_by_key(|result| order.get(&(result.start, result.original_sentence.as_str())).copied().unwrap_or(usize::MAX));
+        write_analysis_jsonl(&output_path, &results)?;
     }
 
     println!("Analysis complete. Output written to {}", output_path.display());
+    if failed > 0 {
+        println!(
+            "{} sentence(s) failed. Run again with --resume {} --retry-failed to retry them.",
+            failed,
+            output_dir.display()
+        );
+    }
 
     Ok(())
 }
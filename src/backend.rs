@@ -0,0 +1,98 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+pub mod mock;
+pub mod ollama;
+pub mod openai;
+
+pub use mock::{MockBackend, RecordingBackend};
+pub use ollama::OllamaBackend;
+pub use openai::OpenAiBackend;
+
+pub const DEFAULT_MODEL: &str = "gemma3:12b";
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+pub const DEFAULT_TEMPERATURE: f32 = 0.3;
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+// 生成パラメータ。None の項目は送らず、サーバー側（Modelfile など）の既定値に任せる
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct GenerationOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    // コンテキスト長（Ollama のみ）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    // 生成する最大トークン数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+}
+
+pub struct GenerateResponse {
+    pub text: String,
+    // 生成トークン数と生成にかかった時間（ナノ秒）。Tokens/s の表示に使う
+    pub eval_count: Option<u64>,
+    pub eval_duration: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackendError {
+    pub message: String,
+    // 接続できない・タイムアウト・5xx・429 など、時間をおいて再試行すれば成功する見込みのあるエラー
+    pub transient: bool,
+}
+
+impl BackendError {
+    pub fn permanent(message: impl Into<String>) -> Self {
+        BackendError { message: message.into(), transient: false }
+    }
+
+    fn from_status(status: reqwest::StatusCode) -> Self {
+        BackendError {
+            message: format!("API request failed with status: {}", status),
+            transient: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+impl From<reqwest::Error> for BackendError {
+    fn from(e: reqwest::Error) -> Self {
+        BackendError {
+            transient: e.is_connect() || e.is_timeout() || e.status().is_some_and(|status| status.is_server_error()),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// 文の解析に使うLLMサーバー。モデル名は各バックエンドが保持する
+pub trait LlmBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn model(&self) -> &str;
+    fn generate<'a>(&'a self, prompt: &'a str) -> BackendFuture<'a, Result<GenerateResponse, BackendError>>;
+    // JSONスキーマに従ったJSONだけを返すよう制約して生成する。text にはJSON文字列が入る
+    fn generate_json<'a>(&'a self, prompt: &'a str, schema: &'a serde_json::Value) -> BackendFuture<'a, Result<GenerateResponse, BackendError>>;
+
+    // 解析を始める前に、モデルが使えることを確かめて読み込んでおく。pull ならサーバーにないモデルを取得する
+    fn prepare(&self, _pull: bool) -> BackendFuture<'_, Result<(), BackendError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+fn build_client(timeout: Duration) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .no_proxy()
+        .http1_only()
+        .build()
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use super::{BackendError, BackendFuture, GenerateResponse, LlmBackend};
+
+// フィクスチャファイル（JSONL）の1行。prompt と schema が完全に一致する応答か、
+// prompt_contains を含むプロンプトへの応答（手で書く固定の応答向け）のどちらか
+#[derive(Serialize, Deserialize)]
+struct FixtureEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prompt_contains: Option<String>,
+    // generate_json で渡されたJSONスキーマ。prompt_contains の行で省略すると、スキーマの有無に関わらず一致する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schema: Option<serde_json::Value>,
+    response: String,
+}
+
+impl FixtureEntry {
+    fn matches(&self, prompt: &str, schema: Option<&serde_json::Value>) -> bool {
+        match (&self.prompt, &self.prompt_contains) {
+            (Some(recorded), _) => recorded == prompt && self.schema.as_ref() == schema,
+            (None, Some(fragment)) => prompt.contains(fragment.as_str()) && (self.schema.is_none() || self.schema.as_ref() == schema),
+            (None, None) => false,
+        }
+    }
+}
+
+// 記録した応答を返すバックエンド（--backend mock --fixture）。サーバーなしで同じ結果を再現できる。
+// 一致する応答がなければ再試行しないエラーにする
+pub struct MockBackend {
+    model: String,
+    entries: Vec<FixtureEntry>,
+}
+
+impl MockBackend {
+    pub fn new(fixture: &Path, model: String) -> io::Result<Self> {
+        let content = fs::read_to_string(fixture).map_err(|e| io::Error::new(e.kind(), format!("Cannot read {}: {}", fixture.display(), e)))?;
+        let mut entries = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: FixtureEntry = serde_json::from_str(line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{} line {}: {}", fixture.display(), index + 1, e))
+            })?;
+            entries.push(entry);
+        }
+        Ok(MockBackend { model, entries })
+    }
+
+    // 完全に一致する行を prompt_contains の行より優先する
+    fn respond(&self, prompt: &str, schema: Option<&serde_json::Value>) -> Result<GenerateResponse, BackendError> {
+        let exact = self.entries.iter().find(|entry| entry.prompt.is_some() && entry.matches(prompt, schema));
+        let entry = exact.or_else(|| self.entries.iter().find(|entry| entry.matches(prompt, schema))).ok_or_else(|| {
+            let first_line = prompt.lines().next().unwrap_or_default();
+            BackendError::permanent(format!("no recorded response for prompt \"{}...\"", first_line.chars().take(60).collect::<String>()))
+        })?;
+        Ok(GenerateResponse { text: entry.response.clone(), eval_count: None, eval_duration: None })
+    }
+}
+
+impl LlmBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn generate<'a>(&'a self, prompt: &'a str) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(async move { self.respond(prompt, None) })
+    }
+
+    fn generate_json<'a>(&'a self, prompt: &'a str, schema: &'a serde_json::Value) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(async move { self.respond(prompt, Some(schema)) })
+    }
+}
+
+// 別のバックエンドへのリクエストと応答をフィクスチャファイルに追記していくバックエンド（--record）
+pub struct RecordingBackend {
+    inner: Arc<dyn LlmBackend>,
+    fixture: Mutex<File>,
+}
+
+impl RecordingBackend {
+    pub fn new(inner: Arc<dyn LlmBackend>, fixture: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(fixture)
+            .map_err(|e| io::Error::new(e.kind(), format!("Cannot open {}: {}", fixture.display(), e)))?;
+        Ok(RecordingBackend { inner, fixture: Mutex::new(file) })
+    }
+
+    fn record(&self, prompt: &str, schema: Option<&serde_json::Value>, response: &GenerateResponse) {
+        let entry = FixtureEntry {
+            prompt: Some(prompt.to_string()),
+            prompt_contains: None,
+            schema: schema.cloned(),
+            response: response.text.clone(),
+        };
+        // 並行して解析していても1行ずつ書き込まれるよう、ロックしてから書く
+        let mut file = self.fixture.lock().unwrap();
+        if let Err(e) = serde_json::to_string(&entry).map_err(io::Error::from).and_then(|line| writeln!(file, "{}", line)) {
+            eprintln!("Warning: failed to record a response: {}", e);
+        }
+    }
+
+    async fn generate_and_record(&self, prompt: &str, schema: Option<&serde_json::Value>) -> Result<GenerateResponse, BackendError> {
+        let response = match schema {
+            Some(schema) => self.inner.generate_json(prompt, schema).await?,
+            None => self.inner.generate(prompt).await?,
+        };
+        self.record(prompt, schema, &response);
+        Ok(response)
+    }
+}
+
+impl LlmBackend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn generate<'a>(&'a self, prompt: &'a str) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(self.generate_and_record(prompt, None))
+    }
+
+    fn generate_json<'a>(&'a self, prompt: &'a str, schema: &'a serde_json::Value) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(self.generate_and_record(prompt, Some(schema)))
+    }
+
+    fn prepare(&self, pull: bool) -> BackendFuture<'_, Result<(), BackendError>> {
+        self.inner.prepare(pull)
+    }
+}
@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::time::{Duration, Instant};
+use super::{build_client, BackendError, BackendFuture, GenerateResponse, GenerationOptions, LlmBackend};
+
+pub const DEFAULT_HOST: &str = "http://localhost:11434";
+
+#[derive(Serialize)]
+struct ApiRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    stream: bool,
+    // 生成パラメータはトップレベルではなく options の中で指定する
+    options: &'a GenerationOptions,
+    // 応答後にモデルをメモリに残しておく時間（"5m", "1h", "-1" など）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<&'a serde_json::Value>,
+    // JSONスキーマを渡すと、それに従うJSONだけを生成する
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    response: String,
+    #[serde(default)]
+    eval_count: Option<u64>,
+    #[serde(default)]
+    eval_duration: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    models: Vec<ModelInfo>,
+}
+
+#[derive(Deserialize)]
+struct ModelInfo {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct PullRequest<'a> {
+    model: &'a str,
+    stream: bool,
+}
+
+// /api/pull が1行ずつ返す進捗
+#[derive(Deserialize)]
+struct PullProgress {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    completed: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+// タグを省略したモデル名は :latest とみなす
+fn same_model(available: &str, requested: &str) -> bool {
+    let has_tag = requested.rsplit('/').next().is_some_and(|name| name.contains(':'));
+    available == requested || (!has_tag && available.strip_suffix(":latest") == Some(requested))
+}
+
+// OLLAMA_HOST と同じく "localhost" や "0.0.0.0:11434" のようなスキームのない指定も受け付ける
+fn normalize_host(host: &str) -> String {
+    let host = host.trim().trim_end_matches('/');
+    if host.contains("://") {
+        return host.to_string();
+    }
+    let has_port = host.rsplit_once(':').is_some_and(|(_, port)| !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()));
+    if has_port { format!("http://{}", host) } else { format!("http://{}:11434", host) }
+}
+
+// Ollama の /api/generate を使うバックエンド
+pub struct OllamaBackend {
+    client: reqwest::Client,
+    host: String,
+    model: String,
+    options: GenerationOptions,
+    keep_alive: Option<serde_json::Value>,
+}
+
+impl OllamaBackend {
+    pub fn new(
+        host: &str,
+        model: String,
+        timeout: Duration,
+        options: GenerationOptions,
+        keep_alive: Option<String>,
+    ) -> reqwest::Result<Self> {
+        Ok(OllamaBackend {
+            client: build_client(timeout)?,
+            host: normalize_host(host),
+            model,
+            options,
+            // "-1" や "3600" のような単位のない値は秒数として数値で送る（文字列だと Go の ParseDuration で失敗する）
+            keep_alive: keep_alive.map(|value| match value.parse::<i64>() {
+                Ok(seconds) => serde_json::Value::from(seconds),
+                Err(_) => serde_json::Value::from(value),
+            }),
+        })
+    }
+
+    async fn call_api(&self, prompt: &str, format: Option<&serde_json::Value>) -> Result<GenerateResponse, BackendError> {
+        let request_body = ApiRequest {
+            model: &self.model,
+            prompt,
+            stream: false,
+            options: &self.options,
+            keep_alive: self.keep_alive.as_ref(),
+            format,
+        };
+
+        let res = self
+            .client
+            .post(format!("{}/api/generate", self.host))
+            .json(&request_body)
+            .send()
+            .await
+            ?;
+
+        if res.status().is_success() {
+            let api_response = res.json::<ApiResponse>().await?;
+            Ok(GenerateResponse {
+                text: api_response.response,
+                eval_count: api_response.eval_count,
+                eval_duration: api_response.eval_duration,
+            })
+        } else {
+            Err(BackendError::from_status(res.status()))
+        }
+    }
+
+    async fn installed_models(&self) -> Result<Vec<String>, BackendError> {
+        let res = self.client.get(format!("{}/api/tags", self.host)).send().await.map_err(|e| {
+            BackendError::permanent(format!("Cannot connect to the Ollama server at {}: {}. Is `ollama serve` running?", self.host, e))
+        })?;
+        if !res.status().is_success() {
+            return Err(BackendError::from_status(res.status()));
+        }
+        let tags = res.json::<TagsResponse>().await?;
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+
+    // /api/pull の進捗を表示しながらモデルを取得する。時間がかかるので --timeout は使わない
+    async fn pull_model(&self) -> Result<(), BackendError> {
+        println!("Pulling model {} from {}...", self.model, self.host);
+        let client = reqwest::Client::builder().connect_timeout(Duration::from_secs(30)).no_proxy().http1_only().build()?;
+        let mut res = client
+            .post(format!("{}/api/pull", self.host))
+            .json(&PullRequest { model: &self.model, stream: true })
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(BackendError::from_status(res.status()));
+        }
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut last_status = String::new();
+        while let Some(chunk) = res.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let Ok(progress) = serde_json::from_slice::<PullProgress>(&line) else {
+                    continue;
+                };
+                if let Some(error) = progress.error {
+                    println!();
+                    return Err(BackendError::permanent(format!("Failed to pull {}: {}", self.model, error)));
+                }
+                // 同じ段階の進捗は同じ行で更新する
+                if progress.status != last_status && !last_status.is_empty() {
+                    println!();
+                }
+                match (progress.total, progress.completed) {
+                    (Some(total), Some(completed)) if total > 0 => print!(
+                        "\r  {}: {:.0}% ({:.1} / {:.1} MB)",
+                        progress.status,
+                        completed as f64 / total as f64 * 100.0,
+                        completed as f64 / 1_000_000.0,
+                        total as f64 / 1_000_000.0
+                    ),
+                    _ => print!("\r  {}", progress.status),
+                }
+                let _ = std::io::stdout().flush();
+                last_status = progress.status;
+            }
+        }
+        println!();
+        if last_status != "success" {
+            return Err(BackendError::permanent(format!("Pulling {} ended without success (last status: {})", self.model, last_status)));
+        }
+        Ok(())
+    }
+
+    // モデルがサーバーにあることを確かめ、空のプロンプトで読み込ませる。最初の文の応答時間に読み込み時間が混ざらないようにする
+    async fn prepare_model(&self, pull: bool) -> Result<(), BackendError> {
+        let models = self.installed_models().await?;
+        if !models.iter().any(|name| same_model(name, &self.model)) {
+            if !pull {
+                return Err(BackendError::permanent(format!(
+                    "Model '{}' is not available on the Ollama server at {}. Run `ollama pull {}` or pass --pull.",
+                    self.model, self.host, self.model
+                )));
+            }
+            self.pull_model().await?;
+        }
+
+        println!("Loading model {}...", self.model);
+        let start = Instant::now();
+        self.call_api("", None).await?;
+        println!("Model loaded in {:.2?}.", start.elapsed());
+        Ok(())
+    }
+}
+
+impl LlmBackend for OllamaBackend {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn generate<'a>(&'a self, prompt: &'a str) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(self.call_api(prompt, None))
+    }
+
+    fn generate_json<'a>(&'a self, prompt: &'a str, schema: &'a serde_json::Value) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(self.call_api(prompt, Some(schema)))
+    }
+
+    fn prepare(&self, pull: bool) -> BackendFuture<'_, Result<(), BackendError>> {
+        Box::pin(self.prepare_model(pull))
+    }
+}
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use super::{build_client, BackendError, BackendFuture, GenerateResponse, GenerationOptions, LlmBackend};
+
+pub const DEFAULT_BASE_URL: &str = "http://localhost:8080/v1";
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ChatResponseMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    #[serde(default)]
+    completion_tokens: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
+}
+
+// OpenAI互換の /chat/completions を使うバックエンド（llama.cpp server, vLLM, LM Studio, LocalAI など）
+pub struct OpenAiBackend {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    options: GenerationOptions,
+}
+
+impl OpenAiBackend {
+    pub fn new(
+        base_url: String,
+        api_key: Option<String>,
+        model: String,
+        timeout: Duration,
+        options: GenerationOptions,
+    ) -> reqwest::Result<Self> {
+        Ok(OpenAiBackend {
+            client: build_client(timeout)?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            options,
+        })
+    }
+
+    async fn call_api(&self, prompt: &str, schema: Option<&serde_json::Value>) -> Result<GenerateResponse, BackendError> {
+        let request_body = ChatRequest {
+            model: &self.model,
+            messages: vec![ChatMessage { role: "user", content: prompt }],
+            stream: false,
+            // num_ctx はサーバーの起動時に決まるため送らない。num_predict は max_tokens に対応する
+            temperature: self.options.temperature,
+            top_p: self.options.top_p,
+            seed: self.options.seed,
+            max_tokens: self.options.num_predict.filter(|&n| n > 0),
+            response_format: schema.map(|schema| {
+                serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": { "name": "response", "strict": true, "schema": schema },
+                })
+            }),
+        };
+
+        let mut request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&request_body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        // 生成時間を返さないサーバーが多いので、リクエスト全体の時間で代用する
+        let start_time = Instant::now();
+        let res = request.send().await?;
+
+        if res.status().is_success() {
+            let chat_response = res.json::<ChatResponse>().await?;
+            let text = chat_response
+                .choices
+                .into_iter()
+                .next()
+                .and_then(|choice| choice.message.content)
+                .ok_or_else(|| BackendError::permanent("API response contained no choices"))?;
+            Ok(GenerateResponse {
+                text,
+                eval_count: chat_response.usage.and_then(|usage| usage.completion_tokens),
+                eval_duration: Some(start_time.elapsed().as_nanos() as u64),
+            })
+        } else {
+            Err(BackendError::from_status(res.status()))
+        }
+    }
+}
+
+impl LlmBackend for OpenAiBackend {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn generate<'a>(&'a self, prompt: &'a str) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(self.call_api(prompt, None))
+    }
+
+    fn generate_json<'a>(&'a self, prompt: &'a str, schema: &'a serde_json::Value) -> BackendFuture<'a, Result<GenerateResponse, BackendError>> {
+        Box::pin(self.call_api(prompt, Some(schema)))
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+// 一時ファイル名の通し番号。同じプロセスの複数のタスクが同じエントリを同時に書いても名前が重ならないようにする
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn hash_hex(data: &str) -> String {
+    Sha256::digest(data.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// $XDG_CACHE_HOME/srtgram、なければ ~/.cache/srtgram（Windows では %LOCALAPPDATA%\srtgram）
+pub fn default_cache_dir() -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("srtgram")
+}
+
+// 同じバックエンド・モデル・プロンプトテンプレートで同じ文を送ったときは、同じ応答を使い回す
+pub struct CacheKey<'a> {
+    pub backend: &'a str,
+    pub model: &'a str,
+    pub template_hash: &'a str,
+    pub sentence: &'a str,
+}
+
+impl CacheKey<'_> {
+    fn digest(&self) -> String {
+        hash_hex(&[self.backend, self.model, self.template_hash, self.sentence].join("\0"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    backend: String,
+    model: String,
+    template_hash: String,
+    sentence: String,
+    response: String,
+}
+
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub oldest: Option<SystemTime>,
+    pub newest: Option<SystemTime>,
+}
+
+struct CacheFile {
+    path: PathBuf,
+    bytes: u64,
+    // 最後に使われた時刻。ヒットするたびに更新するので、prune は使われていないものから消す
+    used: SystemTime,
+}
+
+// LLMの応答をキーのハッシュごとに1ファイルで保存する。<dir>/<ハッシュの先頭2文字>/<ハッシュ>.json
+pub struct ResponseCache {
+    dir: PathBuf,
+}
+
+impl ResponseCache {
+    pub fn new(dir: PathBuf) -> Self {
+        ResponseCache { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, digest: &str) -> PathBuf {
+        self.dir.join(&digest[..2]).join(format!("{}.json", digest))
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<String> {
+        let path = self.entry_path(&key.digest());
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        if entry.backend != key.backend || entry.model != key.model || entry.template_hash != key.template_hash || entry.sentence != key.sentence {
+            return None;
+        }
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(entry.response)
+    }
+
+    pub fn put(&self, key: &CacheKey, response: &str) -> io::Result<()> {
+        let digest = key.digest();
+        let path = self.entry_path(&digest);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let entry = CacheEntry {
+            backend: key.backend.to_string(),
+            model: key.model.to_string(),
+            template_hash: key.template_hash.to_string(),
+            sentence: key.sentence.to_string(),
+            response: response.to_string(),
+        };
+        // 並行して書き込む他のプロセスやタスクが書きかけのファイルを読まないよう、一時ファイルから置き換える
+        let temp_path = path.with_extension(format!("{}.{}.tmp", std::process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        fs::write(&temp_path, serde_json::to_string(&entry)?)?;
+        fs::rename(&temp_path, &path)
+    }
+
+    // <ハッシュの先頭2文字>/<ハッシュ>.json の形のファイルだけをキャッシュのエントリとみなす。
+    // --cache-dir に他のファイルがあっても消さないようにする
+    fn shards(&self) -> io::Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut shards = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let is_shard = entry.file_name().to_str().is_some_and(|name| name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit()));
+            if is_shard && entry.file_type()?.is_dir() {
+                shards.push(entry.path());
+            }
+        }
+        Ok(shards)
+    }
+
+    fn files(&self) -> io::Result<Vec<CacheFile>> {
+        let mut files = Vec::new();
+        for shard in self.shards()? {
+            let prefix = shard.file_name().unwrap().to_string_lossy().to_string();
+            for entry in fs::read_dir(&shard)? {
+                let entry = entry?;
+                let path = entry.path();
+                let is_entry = path.extension().is_some_and(|ext| ext == "json")
+                    && path.file_stem().and_then(|stem| stem.to_str()).is_some_and(|stem| {
+                        stem.len() == 64 && stem.starts_with(&prefix) && stem.bytes().all(|b| b.is_ascii_hexdigit())
+                    });
+                if !is_entry || !entry.file_type()?.is_file() {
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                files.push(CacheFile {
+                    path,
+                    bytes: metadata.len(),
+                    used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+        Ok(files)
+    }
+
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        let files = self.files()?;
+        Ok(CacheStats {
+            entries: files.len(),
+            bytes: files.iter().map(|file| file.bytes).sum(),
+            oldest: files.iter().map(|file| file.used).min(),
+            newest: files.iter().map(|file| file.used).max(),
+        })
+    }
+
+    // older_than より長く使われていないものを消し、さらに合計が max_bytes を超えていれば古いものから消す。
+    // 消したエントリ数とバイト数を返す
+    pub fn prune(&self, older_than: Option<Duration>, max_bytes: Option<u64>) -> io::Result<(usize, u64)> {
+        let mut files = self.files()?;
+        files.sort_by_key(|file| file.used);
+
+        let now = SystemTime::now();
+        let mut total: u64 = files.iter().map(|file| file.bytes).sum();
+        let (mut removed, mut removed_bytes) = (0, 0);
+        for file in files {
+            let expired = older_than.is_some_and(|age| now.duration_since(file.used).unwrap_or_default() > age);
+            let over_size = max_bytes.is_some_and(|max| total > max);
+            if !expired && !over_size {
+                continue;
+            }
+            fs::remove_file(&file.path)?;
+            total -= file.bytes;
+            removed += 1;
+            removed_bytes += file.bytes;
+        }
+        self.remove_empty_shards()?;
+        Ok((removed, removed_bytes))
+    }
+
+    // エントリだけを消す。--cache-dir 自体とキャッシュ以外のファイルは残す
+    pub fn clear(&self) -> io::Result<(usize, u64)> {
+        let files = self.files()?;
+        for file in &files {
+            fs::remove_file(&file.path)?;
+        }
+        self.remove_empty_shards()?;
+        Ok((files.len(), files.iter().map(|file| file.bytes).sum()))
+    }
+
+    // 空になったシャードのディレクトリだけを消す。他のファイルが残っていれば消さない
+    fn remove_empty_shards(&self) -> io::Result<()> {
+        for shard in self.shards()? {
+            if fs::read_dir(&shard)?.next().is_none() {
+                fs::remove_dir(&shard)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::analyzer::AnalysisResult;
+use crate::grammar::GrammarTaxonomy;
+use crate::language::{self, Language};
+
+// よく使われる語の一覧（おおよその頻度順）。一覧のない言語ではまれな語の割合を使わない
+const ENGLISH_FREQUENCY: &str = include_str!("../frequency/en.txt");
+
+// 節を作る文法事項のID（組み込みの grammar_taxonomy.txt と同じ）。付いたタグの数を節の深さの目安にする。
+// 独自の一覧がこれらのIDを1つも含まなければ、節の数は数えない（has_clause_tags）
+const CLAUSE_TAGS: &[&str] = &[
+    "relative_clause",
+    "noun_clause",
+    "adverbial_clause",
+    "participle",
+    "conditional_zero",
+    "conditional_first",
+    "conditional_second",
+    "conditional_third",
+    "wish",
+    "reported_speech",
+    "cleft_sentence",
+];
+
+// 一覧のタグから節の数を数えられるかどうか
+pub fn has_clause_tags(taxonomy: &GrammarTaxonomy) -> bool {
+    taxonomy.tags.iter().any(|tag| CLAUSE_TAGS.contains(&tag.id.as_str()))
+}
+
+// 各特徴量がこの値以上なら最も難しい（100）とみなす
+const MAX_WORDS: f64 = 30.0;
+const MAX_RARE_WORD_RATIO: f64 = 0.3;
+const MAX_CLAUSES: f64 = 3.0;
+
+// 各特徴量の重み。使えない特徴量は除いて重み付き平均をとる
+const WORDS_WEIGHT: f64 = 0.3;
+const RARE_WORDS_WEIGHT: f64 = 0.4;
+const CLAUSES_WEIGHT: f64 = 0.3;
+
+// この点数未満を easy、MEDIUM_BELOW 未満を medium、それ以上を hard と数える
+const EASY_BELOW: f64 = 33.0;
+const MEDIUM_BELOW: f64 = 66.0;
+
+pub struct WordFrequency {
+    ranks: HashMap<&'static str, usize>,
+}
+
+impl WordFrequency {
+    pub fn for_language(language: &Language) -> Option<Self> {
+        if language != language::ENGLISH {
+            return None;
+        }
+        let words = ENGLISH_FREQUENCY.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        Some(WordFrequency { ranks: words.enumerate().map(|(rank, word)| (word, rank + 1)).collect() })
+    }
+
+    // 一覧での順位（1から）。短縮形と規則的な活用形は元の語で探す
+    fn rank(&self, word: &str) -> Option<usize> {
+        let word = word.to_lowercase().replace('’', "'");
+        let word = match word.as_str() {
+            "can't" => "can",
+            "won't" => "will",
+            "shan't" => "shall",
+            _ => word.as_str(),
+        };
+        let base = ["n't", "'s", "'re", "'ll", "'ve", "'d", "'m", "s'"].iter().find_map(|suffix| word.strip_suffix(suffix)).unwrap_or(word);
+
+        let mut candidates = vec![base.to_string()];
+        for (suffix, replacement) in [("ies", "y"), ("ied", "y"), ("es", ""), ("s", ""), ("ed", ""), ("ed", "e"), ("ing", ""), ("ing", "e"), ("ly", ""), ("er", ""), ("est", "")] {
+            if let Some(stem) = base.strip_suffix(suffix).filter(|stem| stem.len() >= 2) {
+                candidates.push(format!("{}{}", stem, replacement));
+                // stopped → stop, running → run
+                let bytes = stem.as_bytes();
+                if replacement.is_empty() && bytes.len() >= 3 && bytes[bytes.len() - 1] == bytes[bytes.len() - 2] {
+                    candidates.push(stem[..stem.len() - 1].to_string());
+                }
+            }
+        }
+        candidates.iter().filter_map(|candidate| self.ranks.get(candidate.as_str()).copied()).min()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Difficulty {
+    // 0〜100。大きいほど難しい
+    pub score: f64,
+    // 語数。空白で区切らない言語では2文字を1語とみなす
+    pub words: usize,
+    // よく使われる語の一覧にない語の割合（固有名詞と数字は除く）。一覧のない言語では None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rare_word_ratio: Option<f64>,
+    // 節を作る文法事項のタグの数。タグ付けしていないか、タグを取得できなければ None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clauses: Option<usize>,
+    // LLMによる1（やさしい）〜5（難しい）の評価（--rate-difficulty）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_rating: Option<u8>,
+}
+
+fn round1(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+// 文の長さ・まれな語の割合・節の数（と、あればLLMの評価）から難易度を計算する
+pub fn score_sentence(
+    sentence: &str,
+    language: &Language,
+    frequency: Option<&WordFrequency>,
+    grammar_tags: Option<&[String]>,
+    llm_rating: Option<u8>,
+) -> Difficulty {
+    let tokens: Vec<&str> = sentence
+        .split_whitespace()
+        .map(|token| token.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|token| !token.is_empty())
+        .collect();
+    let words = if language.without_spaces {
+        sentence.chars().filter(|c| c.is_alphanumeric()).count().div_ceil(2)
+    } else {
+        tokens.len()
+    };
+
+    // 文頭以外で大文字から始まる語は固有名詞とみなす（I は一覧にある）
+    let rare_word_ratio = frequency.map(|frequency| {
+        let counted: Vec<&str> = tokens
+            .iter()
+            .enumerate()
+            .filter(|&(index, &token)| !token.chars().any(|c| c.is_ascii_digit()) && (index == 0 || token == "I" || !token.starts_with(char::is_uppercase)))
+            .map(|(_, &token)| token)
+            .collect();
+        if counted.is_empty() {
+            0.0
+        } else {
+            counted.iter().filter(|token| frequency.rank(token).is_none()).count() as f64 / counted.len() as f64
+        }
+    });
+    let clauses = grammar_tags.map(|tags| tags.iter().filter(|tag| CLAUSE_TAGS.contains(&tag.as_str())).count());
+
+    let mut features = vec![(WORDS_WEIGHT, words as f64 / MAX_WORDS)];
+    if let Some(ratio) = rare_word_ratio {
+        features.push((RARE_WORDS_WEIGHT, ratio / MAX_RARE_WORD_RATIO));
+    }
+    if let Some(clauses) = clauses {
+        features.push((CLAUSES_WEIGHT, clauses as f64 / MAX_CLAUSES));
+    }
+    let total_weight: f64 = features.iter().map(|(weight, _)| weight).sum();
+    let local = features.iter().map(|(weight, value)| weight * value.min(1.0)).sum::<f64>() / total_weight * 100.0;
+    // LLMの評価があれば、ローカルの特徴量による点数と半々で混ぜる
+    let score = match llm_rating {
+        Some(rating) => (local + f64::from(rating - 1) / 4.0 * 100.0) / 2.0,
+        None => local,
+    };
+
+    Difficulty {
+        score: round1(score),
+        words,
+        rare_word_ratio: rare_word_ratio.map(|ratio| (ratio * 1000.0).round() / 1000.0),
+        clauses,
+        llm_rating,
+    }
+}
+
+// metadata.json に入れる動画全体の難易度
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DifficultySummary {
+    // 難易度を計算した文の数
+    pub sentences: usize,
+    pub mean: f64,
+    pub median: f64,
+    // 90パーセンタイル。難しい文がどのくらい混ざるかの目安
+    pub p90: f64,
+    pub max: f64,
+    pub easy: usize,
+    pub medium: usize,
+    pub hard: usize,
+    pub mean_words: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_rare_word_ratio: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_llm_rating: Option<f64>,
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+pub fn summarize(results: &[AnalysisResult]) -> Option<DifficultySummary> {
+    let difficulties: Vec<&Difficulty> = results.iter().filter_map(|result| result.difficulty.as_ref()).collect();
+    let mut scores: Vec<f64> = difficulties.iter().map(|difficulty| difficulty.score).collect();
+    if scores.is_empty() {
+        return None;
+    }
+    scores.sort_by(f64::total_cmp);
+    let percentile = |p: f64| scores[((scores.len() - 1) as f64 * p).round() as usize];
+
+    Some(DifficultySummary {
+        sentences: scores.len(),
+        mean: round1(mean(scores.iter().copied())?),
+        median: percentile(0.5),
+        p90: percentile(0.9),
+        max: scores[scores.len() - 1],
+        easy: scores.iter().filter(|score| **score < EASY_BELOW).count(),
+        medium: scores.iter().filter(|score| (EASY_BELOW..MEDIUM_BELOW).contains(*score)).count(),
+        hard: scores.iter().filter(|score| **score >= MEDIUM_BELOW).count(),
+        mean_words: round1(mean(difficulties.iter().map(|difficulty| difficulty.words as f64))?),
+        mean_rare_word_ratio: mean(difficulties.iter().filter_map(|difficulty| difficulty.rare_word_ratio)).map(|ratio| (ratio * 1000.0).round() / 1000.0),
+        mean_llm_rating: mean(difficulties.iter().filter_map(|difficulty| difficulty.llm_rating.map(f64::from))).map(round1),
+    })
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use crate::cache::hash_hex;
+use crate::language::{self, Language};
+
+// --prompt-dir にこの名前のファイルがあれば、組み込みの一覧の代わりに使う
+pub const TAXONOMY_FILE: &str = "grammar_taxonomy.txt";
+
+// 英語の字幕用の組み込みの一覧。ラベルは日本語で解説するときは日本語、それ以外は英語
+const ENGLISH_JAPANESE_TAXONOMY: &str = include_str!("../grammar/en-ja.txt");
+const ENGLISH_TAXONOMY: &str = include_str!("../grammar/en.txt");
+
+// 英語以外の字幕には組み込みの一覧がない（--prompt-dir に grammar_taxonomy.txt を置けば使える）
+fn default_taxonomy(source: &Language, target: &Language) -> Option<&'static str> {
+    match (source == language::ENGLISH, target == language::JAPANESE) {
+        (true, true) => Some(ENGLISH_JAPANESE_TAXONOMY),
+        (true, false) => Some(ENGLISH_TAXONOMY),
+        (false, _) => None,
+    }
+}
+
+pub struct GrammarTag {
+    pub id: String,
+    pub label: String,
+}
+
+// 文にタグ付けする文法事項の一覧
+pub struct GrammarTaxonomy {
+    pub tags: Vec<GrammarTag>,
+    hash: String,
+}
+
+impl GrammarTaxonomy {
+    // 1行に1つ "ID: ラベル" の形で書く。空行と # で始まる行は無視する
+    pub fn parse(name: &str, text: &str) -> io::Result<Self> {
+        let invalid = |line_number: usize, message: String| {
+            io::Error::new(ErrorKind::InvalidData, format!("grammar taxonomy '{}' line {}: {}", name, line_number, message))
+        };
+        let mut tags: Vec<GrammarTag> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (id, label) = line.split_once(':').ok_or_else(|| invalid(index + 1, "expected 'id: label'".to_string()))?;
+            let (id, label) = (id.trim(), label.trim());
+            if id.is_empty() || !id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+                return Err(invalid(index + 1, format!("invalid id '{}' (use lowercase letters, digits and '_')", id)));
+            }
+            if tags.iter().any(|tag| tag.id == id) {
+                return Err(invalid(index + 1, format!("duplicate id '{}'", id)));
+            }
+            tags.push(GrammarTag { id: id.to_string(), label: if label.is_empty() { id } else { label }.to_string() });
+        }
+        Ok(GrammarTaxonomy { tags, hash: hash_hex(text.trim_end()) })
+    }
+
+    // dir に grammar_taxonomy.txt があればそれを、なければ言語の組み合わせに合った組み込みの一覧を使う。
+    // 一覧がないか空なら None（タグ付けしない）
+    pub fn load(dir: Option<&Path>, source: &Language, target: &Language) -> io::Result<Option<Self>> {
+        let taxonomy = match dir.map(|dir| dir.join(TAXONOMY_FILE)) {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(&path).map_err(|e| io::Error::new(e.kind(), format!("Cannot read {}: {}", path.display(), e)))?;
+                println!("Using grammar taxonomy {}", path.display());
+                GrammarTaxonomy::parse(&path.display().to_string(), &text)?
+            }
+            _ => match default_taxonomy(source, target) {
+                Some(text) => GrammarTaxonomy::parse(TAXONOMY_FILE, text)?,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(taxonomy).filter(|taxonomy| !taxonomy.tags.is_empty()))
+    }
+
+    // 一覧の本文のSHA-256。analysis.jsonl に記録し、どの一覧でタグ付けしたかを区別する
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn ids(&self) -> Vec<&str> {
+        self.tags.iter().map(|tag| tag.id.as_str()).collect()
+    }
+
+    pub fn label(&self, id: &str) -> Option<&str> {
+        self.tags.iter().find(|tag| tag.id == id).map(|tag| tag.label.as_str())
+    }
+
+    // プロンプトの {grammar_tags} に入る一覧
+    pub fn prompt_list(&self) -> String {
+        self.tags.iter().map(|tag| format!("- {}: {}", tag.id, tag.label)).collect::<Vec<_>>().join("\n")
+    }
+}
+
+// 組み込みの一覧を編集用に書き出す。既存のファイルは上書きしない
+pub fn write_default_taxonomy(dir: &Path, source: &Language, target: &Language) -> io::Result<()> {
+    let Some(text) = default_taxonomy(source, target) else {
+        println!("No built-in grammar taxonomy for {} subtitles; write {} yourself to enable grammar tags.", source.name, TAXONOMY_FILE);
+        return Ok(());
+    };
+    let path = dir.join(TAXONOMY_FILE);
+    if path.exists() {
+        println!("Skipped {} (already exists)", path.display());
+        return Ok(());
+    }
+    fs::write(&path, text)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
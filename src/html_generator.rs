@@ -1,9 +1,11 @@
 use pulldown_cmark::{html, Options, Parser};
 use regex::Regex;
-use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, Write};
+use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
-use crate::analyzer::AnalysisResult;
+use crate::analyzer::{read_analysis_jsonl, AnalysisStatus, CefrLevel};
+use crate::grammar::GrammarTaxonomy;
+use crate::language::{self, Language};
 
 fn get_youtube_embed_url(url: &str) -> Option<String> {
     let re = Regex::new(r"(?:watch\?v=|youtu\.be/)([\w-]+)").unwrap();
@@ -21,16 +23,50 @@ fn escape_html(text: &str) -> String {
         .replace("'", "&#39;")
 }
 
-fn srt_time_to_seconds(time_str: &str) -> f64 {
-    let parts: Vec<&str> = time_str.split(&[':', ','][..]).collect();
-    if parts.len() == 4 {
-        let h: f64 = parts[0].parse().unwrap_or(0.0);
-        let m: f64 = parts[1].parse().unwrap_or(0.0);
-        let s: f64 = parts[2].parse().unwrap_or(0.0);
-        let ms: f64 = parts[3].parse().unwrap_or(0.0);
-        h * 3600.0 + m * 60.0 + s + ms / 1000.0
-    } else {
-        0.0
+// ビューアの表示文字列。翻訳・解説の言語に合わせる（未対応の言語は英語）
+struct UiText {
+    click_hint: &'static str,
+    vocabulary: &'static str,
+    grammar_points: &'static str,
+    analysis_failed: &'static str,
+    level_filter: &'static str,
+    all: &'static str,
+}
+
+fn ui_text(source: &Language, target: &Language) -> UiText {
+    match target.code {
+        "ja" => UiText {
+            click_hint: if source == language::ENGLISH { "各英文をクリックすると、解説が開閉します。" } else { "各文をクリックすると、解説が開閉します。" },
+            vocabulary: "語彙",
+            grammar_points: "文法事項",
+            analysis_failed: "解析に失敗しました",
+            level_filter: "語彙のレベル",
+            all: "すべて",
+        },
+        "ko" => UiText {
+            click_hint: "각 문장을 클릭하면 해설이 열리고 닫힙니다.",
+            vocabulary: "어휘",
+            grammar_points: "문법 사항",
+            analysis_failed: "분석에 실패했습니다",
+            level_filter: "어휘 수준",
+            all: "전체",
+        },
+        "es" => UiText {
+            click_hint: "Haz clic en cada oración para mostrar u ocultar la explicación.",
+            vocabulary: "Vocabulario",
+            grammar_points: "Puntos gramaticales",
+            analysis_failed: "No se pudo analizar",
+            level_filter: "Nivel del vocabulario",
+            all: "Todos",
+        },
+        _ => UiText {
+            click_hint: "Click a sentence to show or hide its explanation.",
+            vocabulary: "Vocabulary",
+            grammar_points: "Grammar points",
+            analysis_failed: "Analysis failed",
+            level_filter: "Vocabulary level",
+            all: "All",
+        },
     }
 }
 
@@ -39,20 +75,12 @@ pub fn generate_html_from_jsonl(
     youtube_url: Option<&str>,
     output_dir: &Path,
     title: &str,
+    source_language: &Language,
+    target_language: &Language,
+    grammar: Option<&GrammarTaxonomy>,
 ) -> io::Result<()> {
-    let file = File::open(jsonl_path)?;
-    let reader = BufReader::new(file);
-
-    let mut results: Vec<AnalysisResult> = Vec::new();
-    for line in reader.lines() {
-        let line = line?;
-        if line.trim().is_empty() {
-            continue;
-        }
-        if let Ok(result) = serde_json::from_str(&line) {
-            results.push(result);
-        }
-    }
+    let ui = ui_text(source_language, target_language);
+    let results = read_analysis_jsonl(jsonl_path)?;
 
     let output_path = output_dir.join("index.html");
     let mut file = fs::File::create(&output_path)?;
@@ -66,26 +94,143 @@ pub fn generate_html_from_jsonl(
             let parser = Parser::new_ext(&item.explanation, options);
             let mut explanation_html = String::new();
             html::push_html(&mut explanation_html, parser);
-            let timestamp_sec = srt_time_to_seconds(&item.timestamp);
-
+            let vocabulary_html = if item.vocabulary.is_empty() {
+                String::new()
+            } else {
+                let items: String = item
+                    .vocabulary
+                    .iter()
+                    .map(|v| {
+                        // 文中の形と違う辞書形と品詞を括弧で添える
+                        let mut details = Vec::new();
+                        if !v.lemma.is_empty() && v.lemma != v.surface {
+                            details.push(v.lemma.as_str());
+                        }
+                        if !v.part_of_speech.is_empty() {
+                            details.push(v.part_of_speech.as_str());
+                        }
+                        let details_html = if details.is_empty() {
+                            String::new()
+                        } else {
+                            format!(r#" <span class="lemma">({})</span>"#, escape_html(&details.join(", ")))
+                        };
+                        let cefr_html = v
+                            .cefr
+                            .map(|level| format!(r#" <span class="cefr cefr-{}">{}</span>"#, level.as_str().to_lowercase(), level.as_str()))
+                            .unwrap_or_default();
+                        format!("<li><strong>{}</strong>{}{}: {}</li>", escape_html(&v.surface), details_html, cefr_html, escape_html(&v.gloss))
+                    })
+                    .collect();
+                format!(r#"<h4>{}</h4><ul class="vocabulary">{}</ul>"#, ui.vocabulary, items)
+            };
+            let grammar_html = if item.grammar_points.is_empty() {
+                String::new()
+            } else {
+                let items: String = item
+                    .grammar_points
+                    .iter()
+                    .map(|g| format!("<li><strong>{}</strong>: {}</li>", escape_html(&g.name), escape_html(&g.description)))
+                    .collect();
+                format!(r#"<h4>{}</h4><ul class="grammar-points">{}</ul>"#, ui.grammar_points, items)
+            };
+            let (entry_class, error_html) = match (&item.status, &item.error) {
+                (AnalysisStatus::Failed, error) => (
+                    "entry failed",
+                    format!(r#"
+                        <div class="analysis-error">{}{}</div>"#, ui.analysis_failed, error.as_deref().map(|e| format!(": {}", escape_html(e))).unwrap_or_default()),
+                ),
+                (AnalysisStatus::Ok, _) => ("entry", String::new()),
+            };
+            // ラベルは一覧から引く。一覧が変わって見つからないIDはそのまま表示する
+            let label_of = |id: &str| grammar.and_then(|grammar| grammar.label(id)).unwrap_or(id).to_string();
+            let tags_html = if item.grammar_tags.is_empty() {
+                String::new()
+            } else {
+                let chips: String = item
+                    .grammar_tags
+                    .iter()
+                    .map(|id| format!(r#"<span class="grammar-tag" data-tag="{}">{}</span>"#, escape_html(id), escape_html(&label_of(id))))
+                    .collect();
+                format!(r#"
+                        <div class="grammar-tags">{}</div>"#, chips)
+            };
+            let mut levels: Vec<&str> = item.vocabulary.iter().filter_map(|v| v.cefr).map(CefrLevel::as_str).collect();
+            levels.sort_unstable();
+            levels.dedup();
+            let speaker_html = item
+                .speaker
+                .as_deref()
+                .map(|speaker| format!(r#"<span class="speaker">{}</span>"#, escape_html(speaker)))
+                .unwrap_or_default();
             format!(
-                r###"                <div class="entry" data-timestamp-sec="{}">
+                r###"                <div class="{}" data-start-sec="{}" data-end-sec="{}" data-cefr="{}" data-grammar-tags="{}">
                     <div class="sentence">
-                        <div><span class="timestamp">{}</span><span class="original-text">{}</span></div>
-                        <div class="japanese-translation">{}</div>
+                        <div><span class="timestamp">{}</span>{}<span class="original-text">{}</span></div>
+                        <div class="japanese-translation">{}</div>{}{}
                     </div>
-                    <div class="explanation">{}</div>
+                    <div class="explanation">{}{}{}</div>
                 </div>"###,
-                timestamp_sec,
-                escape_html(&item.timestamp),
+                entry_class,
+                item.start.as_secs_f64(),
+                item.end.as_secs_f64(),
+                levels.join(" "),
+                escape_html(&item.grammar_tags.join(" ")),
+                escape_html(&item.start.to_string()),
+                speaker_html,
                 escape_html(&item.original_sentence),
                 escape_html(&item.translation),
-                explanation_html
+                tags_html,
+                error_html,
+                explanation_html,
+                vocabulary_html,
+                grammar_html
             )
         })
         .collect::<Vec<String>>()
         .join("\n");
 
+    // CEFRレベルの付いた語彙や文法事項のタグがある場合だけ、それで絞り込むメニューを出す
+    let mut filters = Vec::new();
+    if results.iter().flat_map(|item| &item.vocabulary).any(|v| v.cefr.is_some()) {
+        let options: String = CefrLevel::ALL[1..]
+            .iter()
+            .map(|level| format!(r#"<option value="{0}">{0}+</option>"#, level.as_str()))
+            .collect();
+        filters.push(format!(
+            r#"<label>{}: <select id="level-filter"><option value="">{}</option>{}</select></label>"#,
+            ui.level_filter, ui.all, options
+        ));
+    }
+    // 一覧の順に、この動画で使われているタグだけを件数付きで並べる
+    let mut tag_counts: Vec<(String, usize)> = Vec::new();
+    for id in results.iter().flat_map(|item| &item.grammar_tags) {
+        match tag_counts.iter_mut().find(|(known, _)| known == id) {
+            Some((_, count)) => *count += 1,
+            None => tag_counts.push((id.clone(), 1)),
+        }
+    }
+    if let Some(grammar) = grammar {
+        tag_counts.sort_by_key(|(id, _)| grammar.tags.iter().position(|tag| tag.id == *id).unwrap_or(usize::MAX));
+    }
+    if !tag_counts.is_empty() {
+        let options: String = tag_counts
+            .iter()
+            .map(|(id, count)| {
+                let label = grammar.and_then(|grammar| grammar.label(id)).unwrap_or(id);
+                format!(r#"<option value="{}">{} ({})</option>"#, escape_html(id), escape_html(label), count)
+            })
+            .collect();
+        filters.push(format!(
+            r#"<label>{}: <select id="grammar-filter"><option value="">{}</option>{}</select></label>"#,
+            ui.grammar_points, ui.all, options
+        ));
+    }
+    let filters_html = if filters.is_empty() {
+        String::new()
+    } else {
+        format!(r#"            <div id="filters">{}</div>"#, filters.join(" "))
+    };
+
     let video_container_html = if let Some(url_str) = youtube_url {
         let iframe_html = if let Some(embed_url) = get_youtube_embed_url(url_str) {
             format!(
@@ -107,7 +252,7 @@ pub fn generate_html_from_jsonl(
 
     let full_html = format!(
         r###"<!DOCTYPE html>
-<html lang="ja">
+<html lang="{lang}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
@@ -225,7 +370,20 @@ pub fn generate_html_from_jsonl(
         .explanation pre code {{ background-color: transparent; padding: 0; }}
         .explanation blockquote {{ padding: 0 1em; color: #6a737d; border-left: 0.25em solid #dfe2e5; }}
         .timestamp {{ font-size: 0.8rem; color: #888; margin-right: 12px; font-weight: normal; background-color: #f0f0f0; padding: 2px 6px; border-radius: 4px; }}
+        .entry.failed .sentence {{ border-left: 4px solid #e74c3c; }}
+        .analysis-error {{ color: #c0392b; font-weight: bold; margin-bottom: 8px; }}
+        .speaker {{ font-size: 0.8rem; color: #fff; margin-right: 8px; font-weight: normal; background-color: #7f8c8d; padding: 2px 6px; border-radius: 4px; }}
         .original-text {{ font-weight: bold; }}
+        #filters {{ text-align: center; margin-bottom: 10px; font-size: 0.9em; color: #555; }}
+        .lemma {{ color: #777; font-size: 0.9em; }}
+        #filters label {{ margin: 0 8px; }}
+        .grammar-tags {{ margin-top: 5px; text-indent: 6.5rem; }}
+        .grammar-tag {{ display: inline-block; text-indent: 0; font-size: 0.75rem; font-weight: normal; color: #2c3e50; background-color: #e8f0f8; border: 1px solid #c5d5e6; padding: 1px 6px; margin: 2px 4px 2px 0; border-radius: 10px; cursor: pointer; }}
+        .grammar-tag.selected {{ background-color: #2980b9; border-color: #2980b9; color: #fff; }}
+        .cefr {{ font-size: 0.75rem; color: #fff; padding: 1px 5px; border-radius: 4px; background-color: #95a5a6; }}
+        .cefr-a1, .cefr-a2 {{ background-color: #27ae60; }}
+        .cefr-b1, .cefr-b2 {{ background-color: #2980b9; }}
+        .cefr-c1, .cefr-c2 {{ background-color: #8e44ad; }}
         .japanese-translation {{
             font-size: 0.9em;
             color: #555;
@@ -241,7 +399,8 @@ pub fn generate_html_from_jsonl(
     <div id="results-wrapper">
         <div id="container">
             <h1>{title}</h1>
-            <p style="text-align:center;">各英文をクリックすると、解説が開閉します。</p>
+            <p style="text-align:center;">{click_hint}</p>
+{filters}
             <div id="results">
 {entries}
             </div>
@@ -249,6 +408,35 @@ pub fn generate_html_from_jsonl(
     </div>
 </div>
 <script>
+    // 語彙のレベルと文法事項で絞り込む。選んだレベル以上の語を含み、選んだ文法事項のタグが付いた文だけを表示する
+    var levelFilter = document.getElementById('level-filter');
+    var grammarFilter = document.getElementById('grammar-filter');
+    function applyFilters() {{
+        const minLevel = levelFilter ? levelFilter.value : '';
+        const tag = grammarFilter ? grammarFilter.value : '';
+        document.querySelectorAll('.entry').forEach(entry => {{
+            const levels = entry.getAttribute('data-cefr').split(' ').filter(level => level);
+            const tags = entry.getAttribute('data-grammar-tags').split(' ');
+            entry.hidden = (minLevel !== '' && !levels.some(level => level >= minLevel)) || (tag !== '' && !tags.includes(tag));
+        }});
+        document.querySelectorAll('.grammar-tag').forEach(chip => {{
+            chip.classList.toggle('selected', chip.getAttribute('data-tag') === tag);
+        }});
+    }}
+    [levelFilter, grammarFilter].forEach(filter => {{
+        if (filter) filter.addEventListener('change', applyFilters);
+    }});
+    // タグをクリックすると、その文法事項で絞り込む（もう一度クリックすると解除）
+    document.querySelectorAll('.grammar-tag').forEach(chip => {{
+        chip.addEventListener('click', event => {{
+            event.stopPropagation();
+            if (!grammarFilter) return;
+            const tag = chip.getAttribute('data-tag');
+            grammarFilter.value = grammarFilter.value === tag ? '' : tag;
+            applyFilters();
+        }});
+    }});
+
     // 1. YouTube Player APIのスクリプトを非同期で読み込む
     var tag = document.createElement('script');
     tag.src = "https://www.youtube.com/iframe_api";
@@ -276,10 +464,11 @@ pub fn generate_html_from_jsonl(
     // 3. プレイヤーの準備ができたときに呼ばれる
     function onPlayerReady(event) {{
         // 全ての文章要素とそのタイムスタンプを収集
-        document.querySelectorAll('.entry[data-timestamp-sec]').forEach(entry => {{
-            sentenceEntries.push({{ 
+        document.querySelectorAll('.entry[data-start-sec]').forEach(entry => {{
+            sentenceEntries.push({{
                 element: entry,
-                time: parseFloat(entry.getAttribute('data-timestamp-sec'))
+                start: parseFloat(entry.getAttribute('data-start-sec')),
+                end: parseFloat(entry.getAttribute('data-end-sec'))
             }});
         }});
 
@@ -313,35 +502,45 @@ pub fn generate_html_from_jsonl(
         const currentTime = player.getCurrentTime();
         let activeEntry = null;
 
-        // 現在時刻に最も近い、過去のタイムスタンプを持つ文章を探す
+        // 現在時刻に最も近い、過去の開始時刻を持つ文章を探す
         for (let i = sentenceEntries.length - 1; i >= 0; i--) {{
-            if (currentTime >= sentenceEntries[i].time - 0.5) {{ // 0.5秒早くハイライト
+            if (currentTime >= sentenceEntries[i].start - 0.5) {{ // 0.5秒早くハイライト
                 activeEntry = sentenceEntries[i];
                 break;
             }}
         }}
 
-        if (activeEntry && activeEntry !== lastActiveEntry) {{
-            // 他のすべてのアクティブクラスを削除
-            document.querySelectorAll('.entry.active-sentence').forEach(entry => {{
-                entry.classList.remove('active-sentence');
-            }});
+        // 文の終了時刻を過ぎたらハイライトを外す（終了時刻が不明な古いデータは次の文まで維持）
+        if (activeEntry && activeEntry.end > activeEntry.start && currentTime > activeEntry.end + 0.5) {{
+            activeEntry = null;
+        }}
+
+        if (activeEntry === lastActiveEntry) return;
+
+        // 他のすべてのアクティブクラスを削除
+        document.querySelectorAll('.entry.active-sentence').forEach(entry => {{
+            entry.classList.remove('active-sentence');
+        }});
 
+        if (activeEntry) {{
             // 新しい文章をアクティブにする
             activeEntry.element.classList.add('active-sentence');
-            
+
             // アクティブな文章が画面内に表示されるようにスクロール
             activeEntry.element.scrollIntoView({{
                 behavior: 'smooth',
                 block: 'center'
             }});
-
-            lastActiveEntry = activeEntry;
         }}
+
+        lastActiveEntry = activeEntry;
     }}
 </script>
 </body>
 </html>"###,
+        lang = target_language.code,
+        click_hint = ui.click_hint,
+        filters = filters_html,
         title = escape_html(title),
         video_container = video_container_html,
         entries = entries_html
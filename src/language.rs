@@ -0,0 +1,40 @@
+use std::io::{self, ErrorKind};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Language {
+    // ISO 639-1 コード。YouTubeの字幕言語とHTMLの lang 属性にも使う
+    pub code: &'static str,
+    // プロンプトの {source_language} / {target_language} に入る英語名
+    pub name: &'static str,
+    // 単語を空白で区切らない言語。文を結合するときに空白を入れない
+    pub without_spaces: bool,
+}
+
+const fn language(code: &'static str, name: &'static str, without_spaces: bool) -> Language {
+    Language { code, name, without_spaces }
+}
+
+pub const LANGUAGES: &[Language] = &[
+    language("en", "English", false),
+    language("ja", "Japanese", true),
+    language("ko", "Korean", false),
+    language("es", "Spanish", false),
+    language("fr", "French", false),
+    language("de", "German", false),
+    language("it", "Italian", false),
+    language("pt", "Portuguese", false),
+    language("ru", "Russian", false),
+    language("zh", "Chinese", true),
+];
+
+pub const ENGLISH: &Language = &LANGUAGES[0];
+pub const JAPANESE: &Language = &LANGUAGES[1];
+
+// "en", "EN", "en-US", "pt_BR" などを受け付ける
+pub fn language_for_code(code: &str) -> io::Result<&'static Language> {
+    let base = code.trim().split(['-', '_']).next().unwrap_or("").to_ascii_lowercase();
+    LANGUAGES.iter().find(|language| language.code == base).ok_or_else(|| {
+        let supported: Vec<&str> = LANGUAGES.iter().map(|language| language.code).collect();
+        io::Error::new(ErrorKind::InvalidInput, format!("Unsupported language '{}' (supported: {})", code, supported.join(", ")))
+    })
+}
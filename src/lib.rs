@@ -0,0 +1,4 @@
+// 字幕の読み込みと文への分割をライブラリとして公開する。srtgram コマンドもこれを使う
+pub mod language;
+pub mod parser;
+pub mod timestamp;
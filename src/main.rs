@@ -1,30 +1,360 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use regex::Regex;
 use std::cmp::min;
 use std::fs;
 use std::io::{self, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use srtgram::{language, parser, timestamp};
 
 mod analyzer;
+mod backend;
+mod cache;
+mod difficulty;
+mod grammar;
 mod html_generator;
 mod metadata_generator;
-pub mod parser;
+mod prompts;
+mod vocabulary_generator;
 mod youtube_downloader;
 
+const DEFAULT_SOURCE_LANG: &str = "en";
+const DEFAULT_TARGET_LANG: &str = "ja";
+
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short = 'l', long, value_name = "FILE")]
     local_file: Option<String>,
 
     #[arg(short = 'y', long, value_name = "URL")]
     youtube_url: Option<String>,
 
+    /// Continue an interrupted run in an existing output directory, analyzing only sentences missing from analysis.jsonl
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["local_file", "youtube_url"])]
+    resume: Option<String>,
+
+    /// With --resume: also re-analyze sentences that failed in the earlier run
+    #[arg(long, requires = "resume")]
+    retry_failed: bool,
+
+    /// Number of retries for transient errors (connection refused, timeouts, 5xx)
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    retries: u32,
+
+    /// Delay before the first retry in milliseconds; doubled on every retry
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    retry_delay: u64,
+
     #[arg(short = 'm', long, value_name = "MODEL")]
     model: Option<String>,
 
+    /// LLM server API used for analysis
+    #[arg(long, value_enum, default_value_t = BackendKind::Ollama)]
+    backend: BackendKind,
+
+    /// Base URL of an OpenAI-compatible server (used with --backend openai)
+    #[arg(long, value_name = "URL", default_value = backend::openai::DEFAULT_BASE_URL)]
+    api_base: String,
+
+    /// API key for an OpenAI-compatible server (defaults to $OPENAI_API_KEY)
+    #[arg(long, value_name = "KEY")]
+    api_key: Option<String>,
+
+    /// JSONL file with the responses for --backend mock
+    #[arg(long, value_name = "FILE", required_if_eq("backend", "mock"))]
+    fixture: Option<PathBuf>,
+
+    /// Append every request and response to this JSONL file for replaying with --backend mock (bypasses the cache)
+    #[arg(long, value_name = "FILE", conflicts_with = "fixture")]
+    record: Option<PathBuf>,
+
+    /// Ollama server address (defaults to $OLLAMA_HOST, then http://localhost:11434)
+    #[arg(long, value_name = "URL")]
+    ollama_host: Option<String>,
+
+    /// Request timeout in seconds
+    #[arg(long, value_name = "SECS", default_value_t = backend::DEFAULT_TIMEOUT.as_secs())]
+    timeout: u64,
+
+    /// Sampling temperature
+    #[arg(long, value_name = "TEMP", default_value_t = backend::DEFAULT_TEMPERATURE)]
+    temperature: f32,
+
+    /// Context window size in tokens (Ollama only; server default if omitted)
+    #[arg(long, value_name = "TOKENS")]
+    num_ctx: Option<u32>,
+
+    /// Random seed for reproducible output
+    #[arg(long, value_name = "SEED")]
+    seed: Option<i64>,
+
+    /// Nucleus sampling threshold
+    #[arg(long, value_name = "P")]
+    top_p: Option<f32>,
+
+    /// Maximum number of tokens to generate per request (-1 for no limit)
+    #[arg(long, value_name = "TOKENS", allow_negative_numbers = true)]
+    num_predict: Option<i32>,
+
+    /// How long Ollama keeps the model loaded after a request, e.g. 10m, 1h, or -1 for forever
+    #[arg(long, value_name = "DURATION", allow_hyphen_values = true)]
+    keep_alive: Option<String>,
+
+    /// Pull the model with Ollama's /api/pull if the server doesn't have it yet
+    #[arg(long)]
+    pull: bool,
+
+    /// Directory of the LLM response cache (defaults to $XDG_CACHE_HOME/srtgram or ~/.cache/srtgram)
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Always ask the model instead of reusing cached responses
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Include N previous and next sentences and the title as context in the prompts ({context} in templates)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    context: usize,
+
+    /// Language of the subtitles, e.g. en, es, ko (default: en)
+    #[arg(long, value_name = "LANG")]
+    source_lang: Option<String>,
+
+    /// Language of translations, explanations and the viewer, e.g. ja, ko, es (default: ja)
+    #[arg(long, value_name = "LANG")]
+    target_lang: Option<String>,
+
+    /// Directory with prompt templates (translation.txt, explanation.txt, vocabulary.txt, grammar_tags.txt, difficulty.txt, structured.txt, punctuation.txt) and a grammar taxonomy (grammar_taxonomy.txt) overriding the built-in ones
+    #[arg(long, value_name = "DIR")]
+    prompt_dir: Option<PathBuf>,
+
+    /// Also ask the model to rate each sentence's difficulty from 1 to 5 and mix it into the difficulty score
+    #[arg(long)]
+    rate_difficulty: bool,
+
+    /// Also extract vocabulary with CEFR levels in a separate JSON-schema request per sentence (always included with --structured)
+    #[arg(long)]
+    vocabulary: bool,
+
+    /// Also tag each sentence with grammar points from the taxonomy in a separate JSON-schema request (always included with --structured)
+    #[arg(long)]
+    grammar_tags: bool,
+
+    /// Get translation, explanation, vocabulary and grammar points in one JSON-schema-constrained request per sentence
+    #[arg(long)]
+    structured: bool,
+
     #[arg(long, value_name = "LIMIT")]
     limit: Option<usize>,
+
+    /// Number of sentences to analyze in parallel (match the server's OLLAMA_NUM_PARALLEL)
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+
+    /// ASS/SSA: only merge Dialogue lines with this Style (repeatable)
+    #[arg(long = "ass-style", value_name = "STYLE")]
+    ass_styles: Vec<String>,
+
+    /// ASS/SSA: only merge Dialogue lines with this Name/actor (repeatable)
+    #[arg(long = "ass-name", value_name = "NAME")]
+    ass_names: Vec<String>,
+
+    /// Remove words repeated between consecutive rolling cues (enabled automatically for YouTube auto-captions)
+    #[arg(long)]
+    dedupe_rolling: bool,
+
+    /// For unpunctuated captions: split sentences at pauses of at least this many milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 800)]
+    pause_gap: u64,
+
+    /// For unpunctuated captions: maximum number of words per sentence
+    #[arg(long, value_name = "WORDS", default_value_t = 30)]
+    max_sentence_words: usize,
+
+    /// For unpunctuated captions: ask the model to restore punctuation before splitting sentences
+    #[arg(long)]
+    restore_punctuation: bool,
+
+    /// Keep cue text as-is (no removal of tags, speaker labels or annotations)
+    #[arg(long)]
+    raw_text: bool,
+
+    /// Keep bracketed annotations such as [Music] or (laughs) in the text
+    #[arg(long)]
+    keep_annotations: bool,
+
+    /// Character encoding of the subtitle file, e.g. utf-16le, windows-1252, shift_jis (auto-detected by default)
+    #[arg(long, value_name = "ENCODING")]
+    encoding: Option<String>,
+
+    /// Abort if the SRT file has validation errors instead of recovering
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum BackendKind {
+    /// Ollama /api/generate
+    Ollama,
+    /// OpenAI-compatible /chat/completions (llama.cpp server, vLLM, LM Studio, LocalAI)
+    Openai,
+    /// Responses recorded with --record (or written by hand) in the --fixture file, without a server
+    Mock,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check a subtitle file and print a line-numbered report of problems
+    Validate {
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Character encoding of the subtitle file (auto-detected by default)
+        #[arg(long, value_name = "ENCODING")]
+        encoding: Option<String>,
+
+        /// Treat warnings as failures
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Write the built-in prompt templates to a directory for editing (use with --prompt-dir)
+    Prompts {
+        #[arg(value_name = "DIR")]
+        dir: PathBuf,
+
+        /// Language of the subtitles the templates are for
+        #[arg(long, value_name = "LANG", default_value = DEFAULT_SOURCE_LANG)]
+        source_lang: String,
+
+        /// Language of the translations and explanations the templates are for
+        #[arg(long, value_name = "LANG", default_value = DEFAULT_TARGET_LANG)]
+        target_lang: String,
+    },
+    /// Inspect or clean up the cache of LLM responses
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+
+        /// Cache directory (defaults to $XDG_CACHE_HOME/srtgram or ~/.cache/srtgram)
+        #[arg(long, value_name = "DIR", global = true)]
+        cache_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Show the location, number of entries and size of the cache
+    Info,
+    /// Remove entries that have not been used recently
+    Prune {
+        /// Remove entries not used for this many days
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+
+        /// Then remove the least recently used entries until the cache is at most this many megabytes
+        #[arg(long, value_name = "MB")]
+        max_size: Option<u64>,
+    },
+    /// Remove all cached responses
+    Clear,
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+fn run_cache_command(action: &CacheCommand, cache_dir: Option<PathBuf>) -> io::Result<()> {
+    let cache = cache::ResponseCache::new(cache_dir.unwrap_or_else(cache::default_cache_dir));
+    match action {
+        CacheCommand::Info => {
+            let stats = cache.stats()?;
+            let format_time = |time: std::time::SystemTime| chrono::DateTime::<chrono::Local>::from(time).format("%Y-%m-%d %H:%M").to_string();
+            println!("Cache directory: {}", cache.dir().display());
+            println!("Entries: {}", stats.entries);
+            println!("Size: {}", format_size(stats.bytes));
+            if let (Some(oldest), Some(newest)) = (stats.oldest, stats.newest) {
+                println!("Last used: {} (oldest) - {} (newest)", format_time(oldest), format_time(newest));
+            }
+        }
+        CacheCommand::Prune { older_than, max_size } => {
+            if older_than.is_none() && max_size.is_none() {
+                return Err(io::Error::new(ErrorKind::InvalidInput, "Specify --older-than and/or --max-size."));
+            }
+            let older_than = older_than.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+            let max_bytes = max_size.map(|mb| mb * 1024 * 1024);
+            let (removed, bytes) = cache.prune(older_than, max_bytes)?;
+            println!("Removed {} entries ({}) from {}", removed, format_size(bytes), cache.dir().display());
+        }
+        CacheCommand::Clear => {
+            let (removed, bytes) = cache.clear()?;
+            println!("Removed {} entries ({}) from {}", removed, format_size(bytes), cache.dir().display());
+        }
+    }
+    Ok(())
+}
+
+fn validate_subtitle_file(file: &str, encoding: Option<&str>, strict: bool) -> io::Result<()> {
+    let path = Path::new(file);
+    let encoding = encoding.map(parser::encoding_for_label).transpose()?;
+    let (content, format, decoding) = parser::read_subtitle_file(path, encoding)?;
+    parser::report_decoding(&decoding);
+    let (cues, diagnostics) = parser::parse_cues(&content, format, &parser::ParseOptions::default());
+
+    for diagnostic in &diagnostics {
+        println!("{}: {}", path.display(), diagnostic);
+    }
+    if format != parser::SubtitleFormat::Srt {
+        println!("Note: detailed validation is only available for SRT files (detected {:?}).", format);
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.severity == parser::Severity::Error).count();
+    let warnings = diagnostics.len() - errors;
+    println!("{}: {} cues, {} error(s), {} warning(s)", path.display(), cues.len(), errors, warnings);
+
+    if errors > 0 || (strict && warnings > 0) {
+        return Err(io::Error::new(ErrorKind::InvalidData, "Validation failed."));
+    }
+    Ok(())
+}
+
+fn create_backend(args: &Args) -> io::Result<Arc<dyn backend::LlmBackend>> {
+    let model = args.model.clone().unwrap_or_else(|| backend::DEFAULT_MODEL.to_string());
+    let timeout = Duration::from_secs(args.timeout);
+    let options = backend::GenerationOptions {
+        temperature: Some(args.temperature),
+        top_p: args.top_p,
+        seed: args.seed,
+        num_ctx: args.num_ctx,
+        num_predict: args.num_predict,
+    };
+    let backend: Arc<dyn backend::LlmBackend> = match args.backend {
+        BackendKind::Ollama => {
+            let host = args
+                .ollama_host
+                .clone()
+                .or_else(|| std::env::var("OLLAMA_HOST").ok().filter(|host| !host.is_empty()))
+                .unwrap_or_else(|| backend::ollama::DEFAULT_HOST.to_string());
+            Arc::new(backend::OllamaBackend::new(&host, model, timeout, options, args.keep_alive.clone()).map_err(io::Error::other)?)
+        }
+        BackendKind::Openai => {
+            let api_key = args.api_key.clone().or_else(|| std::env::var("OPENAI_API_KEY").ok());
+            Arc::new(backend::OpenAiBackend::new(args.api_base.clone(), api_key, model, timeout, options).map_err(io::Error::other)?)
+        }
+        BackendKind::Mock => Arc::new(backend::MockBackend::new(args.fixture.as_deref().unwrap(), model)?),
+    };
+    match &args.record {
+        Some(fixture) => Ok(Arc::new(backend::RecordingBackend::new(backend, fixture)?)),
+        None => Ok(backend),
+    }
 }
 
 fn get_youtube_id(url: &str) -> Option<String> {
@@ -53,51 +383,148 @@ fn create_output_directory(base_name: &str) -> io::Result<PathBuf> {
 async fn main() -> io::Result<()> {
     let args = Args::parse();
 
-    let base_name = if let Some(local_file) = &args.local_file {
-        PathBuf::from(local_file).file_stem().unwrap_or_default().to_string_lossy().to_string()
-    } else if let Some(youtube_url) = &args.youtube_url {
-        get_youtube_id(youtube_url).ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Invalid YouTube URL"))?
-    } else {
-        eprintln!("Usage: srtgram -l <FILE> | -y <URL>");
-        return Err(io::Error::new(ErrorKind::InvalidInput, "No input specified."));
+    match &args.command {
+        Some(Command::Validate { file, encoding, strict }) => return validate_subtitle_file(file, encoding.as_deref(), *strict),
+        Some(Command::Prompts { dir, source_lang, target_lang }) => {
+            let (source, target) = (language::language_for_code(source_lang)?, language::language_for_code(target_lang)?);
+            prompts::write_default_templates(dir, source, target)?;
+            return grammar::write_default_taxonomy(dir, source, target);
+        }
+        Some(Command::Cache { action, cache_dir }) => return run_cache_command(action, cache_dir.clone()),
+        None => {}
+    }
+
+    let encoding = args.encoding.as_deref().map(parser::encoding_for_label).transpose()?;
+    let llm_backend = create_backend(&args)?;
+
+    // 再開時は、指定がなければ前回の実行と同じ言語を使う
+    let resumed_metadata = args.resume.as_ref().map(|dir| metadata_generator::load_metadata(Path::new(dir))).transpose()?;
+    let source_language = language::language_for_code(
+        args.source_lang.as_deref().or(resumed_metadata.as_ref().and_then(|m| m.source_language.as_deref())).unwrap_or(DEFAULT_SOURCE_LANG),
+    )?;
+    let target_language = language::language_for_code(
+        args.target_lang.as_deref().or(resumed_metadata.as_ref().and_then(|m| m.target_language.as_deref())).unwrap_or(DEFAULT_TARGET_LANG),
+    )?;
+    let prompt_templates = Arc::new(prompts::PromptTemplates::load(args.prompt_dir.as_deref(), source_language, target_language)?);
+    let grammar_taxonomy = grammar::GrammarTaxonomy::load(args.prompt_dir.as_deref(), source_language, target_language)?.map(Arc::new);
+
+    let retry_policy = analyzer::RetryPolicy {
+        max_retries: args.retries,
+        initial_delay: Duration::from_millis(args.retry_delay),
     };
+    // 記録するときはすべての応答をモデルから取得する
+    let response_cache = (!args.no_cache && args.record.is_none()).then(|| Arc::new(cache::ResponseCache::new(args.cache_dir.clone().unwrap_or_else(cache::default_cache_dir))));
 
-    let output_dir = create_output_directory(&base_name)?;
-    println!("Output will be saved in: {}", output_dir.display());
-
-    let (srt_path, youtube_url_opt, html_title, duration_opt, thumbnail_path_opt) = if let Some(local_file) = &args.local_file {
-        let path = PathBuf::from(local_file);
-        let new_srt_path = output_dir.join(path.file_name().unwrap());
-        fs::copy(&path, &new_srt_path)?;
-        let title = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
-        (new_srt_path, None, title, None, None)
-    } else if let Some(youtube_url) = &args.youtube_url {
-        let downloaded_srt_path = youtube_downloader::download_youtube_subtitles(youtube_url, &output_dir).await?;
-        let title = youtube_downloader::get_youtube_video_title(youtube_url).await?;
-        let duration = youtube_downloader::get_youtube_video_duration(youtube_url).await.ok();
-        let thumbnail_path = youtube_downloader::download_youtube_thumbnail(youtube_url, &output_dir).await.ok();
-        
-        let relative_thumbnail_path = thumbnail_path.map(|_| 
-            format!("{}/thumbnail.png", output_dir.file_name().unwrap().to_string_lossy())
-        );
+    // モデルがなければ、全文の解析が失敗する前にここで止める（入力がなければ下で使い方を表示する）
+    if args.resume.is_some() || args.local_file.is_some() || args.youtube_url.is_some() {
+        llm_backend.prepare(args.pull).await.map_err(|e| io::Error::other(e.to_string()))?;
+    }
 
-        (downloaded_srt_path, Some(youtube_url.clone()), title, duration, relative_thumbnail_path)
+    let (output_dir, subtitles, youtube_url_opt, html_title, duration_opt, thumbnail_path_opt) = if let (Some(resume_dir), Some(metadata)) = (&args.resume, resumed_metadata) {
+        let output_dir = PathBuf::from(resume_dir);
+        let subtitles = parser::read_sentences_json(&output_dir)?;
+        println!("Resuming analysis in: {}", output_dir.display());
+        (output_dir, subtitles, metadata.video_url, metadata.title, metadata.duration, metadata.thumbnail_path)
     } else {
-        return Err(io::Error::new(ErrorKind::InvalidInput, "No input specified."));
-    };
+        let base_name = if let Some(local_file) = &args.local_file {
+            PathBuf::from(local_file).file_stem().unwrap_or_default().to_string_lossy().to_string()
+        } else if let Some(youtube_url) = &args.youtube_url {
+            get_youtube_id(youtube_url).ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Invalid YouTube URL"))?
+        } else {
+            eprintln!("Usage: srtgram -l <FILE> | -y <URL> | --resume <DIR> | validate <FILE>");
+            return Err(io::Error::new(ErrorKind::InvalidInput, "No input specified."));
+        };
 
-    let sentences_json_path = parser::process_srt_file(&srt_path, &output_dir)?;
+        let output_dir = create_output_directory(&base_name)?;
+        println!("Output will be saved in: {}", output_dir.display());
+
+        let (srt_path, youtube_url_opt, html_title, duration_opt, thumbnail_path_opt, is_auto_caption) = if let Some(local_file) = &args.local_file {
+            let path = PathBuf::from(local_file);
+            let new_srt_path = output_dir.join(path.file_name().unwrap());
+            fs::copy(&path, &new_srt_path)?;
+            let title = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            (new_srt_path, None, title, None, None, false)
+        } else if let Some(youtube_url) = &args.youtube_url {
+            let (downloaded_srt_path, is_auto_caption) = youtube_downloader::download_youtube_subtitles(youtube_url, &output_dir, source_language).await?;
+            let title = youtube_downloader::get_youtube_video_title(youtube_url).await?;
+            let duration = youtube_downloader::get_youtube_video_duration(youtube_url).await.ok();
+            let thumbnail_path = youtube_downloader::download_youtube_thumbnail(youtube_url, &output_dir).await.ok();
+        
+            let relative_thumbnail_path = thumbnail_path.map(|_| 
+                format!("{}/thumbnail.png", output_dir.file_name().unwrap().to_string_lossy())
+            );
+
+            (downloaded_srt_path, Some(youtube_url.clone()), title, duration, relative_thumbnail_path, is_auto_caption)
+        } else {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "No input specified."));
+        };
+
+        let parse_options = parser::ParseOptions {
+            ass_styles: args.ass_styles.clone(),
+            ass_names: args.ass_names.clone(),
+            dedupe_rolling: args.dedupe_rolling || is_auto_caption,
+            clean_text: !args.raw_text,
+            keep_annotations: args.keep_annotations,
+            encoding,
+            strict: args.strict,
+            pause_gap: Duration::from_millis(args.pause_gap),
+            max_sentence_words: args.max_sentence_words,
+            language: source_language,
+        };
+        let mut cues = parser::load_cues(&srt_path, &parse_options)?;
+        if parser::is_unpunctuated(&cues, source_language) {
+            if args.restore_punctuation {
+                let client = analyzer::LlmClient::new(llm_backend.clone(), response_cache.clone(), retry_policy);
+                analyzer::restore_punctuation(&mut cues, &client, &prompt_templates, source_language, target_language)
+                    .await
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+            }
+            if parser::is_unpunctuated(&cues, source_language) {
+                println!("Subtitles have almost no punctuation. Splitting sentences by pauses (>= {:?}) and length (<= {} words).", parse_options.pause_gap, parse_options.max_sentence_words);
+            }
+        }
+        let subtitles = parser::merge_into_sentences(&cues, &parse_options);
+        parser::write_sentences_json(&subtitles, &output_dir)?;
+
+        // 解析が途中で止まっても --resume で再開できるよう、解析前にメタデータを保存しておく
+        let sentence_count = args.limit.map_or(subtitles.len(), |l| min(subtitles.len(), l));
+        metadata_generator::generate_and_save_metadata(
+            &output_dir,
+            html_title.clone(),
+            youtube_url_opt.clone(),
+            duration_opt.clone(),
+            sentence_count,
+            thumbnail_path_opt.clone(),
+            (source_language, target_language),
+        )?;
+
+        (output_dir, subtitles, youtube_url_opt, html_title, duration_opt, thumbnail_path_opt)
+    };
 
-    let sentences_content = fs::read_to_string(&sentences_json_path)?;
-    let subtitles: Vec<parser::Subtitle> = serde_json::from_str(&sentences_content)
-        .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("Failed to parse sentences.json: {}", e)))
-        ?;
     let sentence_count = args.limit.map_or(subtitles.len(), |l| min(subtitles.len(), l));
 
-    analyzer::analyze_sentences_from_json(&sentences_json_path, args.model, &output_dir, args.limit).await.map_err(|e| io::Error::new(ErrorKind::Other, e.to_string()))?;
+    let analysis_options = analyzer::AnalysisOptions {
+        limit: args.limit,
+        concurrency: args.concurrency.into(),
+        resume: args.resume.is_some(),
+        retry_failed: args.retry_failed,
+        retry: retry_policy,
+        structured: args.structured,
+        prompts: prompt_templates,
+        title: html_title.clone(),
+        context: args.context,
+        grammar: grammar_taxonomy.clone().filter(|_| args.grammar_tags || args.structured),
+        rate_difficulty: args.rate_difficulty,
+        vocabulary: args.vocabulary || args.structured,
+        source_language,
+        target_language,
+        cache: response_cache,
+    };
+    analyzer::analyze_sentences(&subtitles, llm_backend.clone(), &output_dir, &analysis_options).await.map_err(|e| io::Error::other(e.to_string()))?;
 
     let analysis_jsonl_path = output_dir.join("analysis.jsonl");
-    html_generator::generate_html_from_jsonl(&analysis_jsonl_path, youtube_url_opt.as_deref(), &output_dir, &html_title)?;
+    html_generator::generate_html_from_jsonl(&analysis_jsonl_path, youtube_url_opt.as_deref(), &output_dir, &html_title, source_language, target_language, grammar_taxonomy.as_deref())?;
+    vocabulary_generator::generate_vocabulary_from_jsonl(&analysis_jsonl_path, &output_dir)?;
 
     metadata_generator::generate_and_save_metadata(
         &output_dir,
@@ -106,6 +533,7 @@ async fn main() -> io::Result<()> {
         duration_opt,
         sentence_count,
         thumbnail_path_opt,
+        (source_language, target_language),
     )?;
 
     println!("\nAll steps completed.");
@@ -1,19 +1,30 @@
 use chrono::Utc;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io;
 use std::path::Path;
+use crate::analyzer::read_analysis_jsonl;
+use crate::difficulty::{self, DifficultySummary};
+use crate::language::Language;
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Metadata {
-    title: String,
-    video_url: Option<String>,
-    duration: Option<String>,
-    sentence_count: usize,
-    thumbnail_path: Option<String>,
-    report_path: String,
-    creation_date: String,
-    output_dir_name: String,
+    pub title: String,
+    pub video_url: Option<String>,
+    pub duration: Option<String>,
+    pub sentence_count: usize,
+    pub thumbnail_path: Option<String>,
+    pub report_path: String,
+    pub creation_date: String,
+    pub output_dir_name: String,
+    // 字幕の言語と翻訳・解説の言語のコード。以前の metadata.json にはない
+    #[serde(default)]
+    pub source_language: Option<String>,
+    #[serde(default)]
+    pub target_language: Option<String>,
+    // 解析済みの文の難易度の集計。解析前や以前の metadata.json にはない
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<DifficultySummary>,
 }
 
 pub fn generate_and_save_metadata(
@@ -23,8 +34,20 @@ pub fn generate_and_save_metadata(
     duration_opt: Option<String>,
     sentence_count: usize,
     thumbnail_path_opt: Option<String>,
+    (source_language, target_language): (&Language, &Language),
 ) -> io::Result<()> {
-    let output_dir_name = output_dir.file_name().unwrap().to_string_lossy().to_string();
+    // --resume . のように渡されたパスには最後の要素がないことがあるので、絶対パスにしてから名前を取る
+    let output_dir_name = output_dir
+        .canonicalize()?
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Cannot use {} as an output directory", output_dir.display())))?;
+    // 解析前に保存するときは analysis.jsonl がまだない
+    let difficulty = match read_analysis_jsonl(&output_dir.join("analysis.jsonl")) {
+        Ok(results) => difficulty::summarize(&results),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
 
     let metadata = Metadata {
         title: html_title,
@@ -35,12 +58,29 @@ pub fn generate_and_save_metadata(
         report_path: format!("{}/index.html", output_dir_name),
         creation_date: Utc::now().to_rfc3339(),
         output_dir_name,
+        source_language: Some(source_language.code.to_string()),
+        target_language: Some(target_language.code.to_string()),
+        difficulty,
     };
 
     let metadata_path = output_dir.join("metadata.json");
     let metadata_json = serde_json::to_string_pretty(&metadata)?;
     fs::write(&metadata_path, metadata_json)?;
     println!("Metadata file created at {}", metadata_path.display());
+    if let Some(difficulty) = &metadata.difficulty {
+        println!(
+            "Difficulty: mean {:.1}, median {:.1}, 90th percentile {:.1} (easy {}, medium {}, hard {} of {} sentences)",
+            difficulty.mean, difficulty.median, difficulty.p90, difficulty.easy, difficulty.medium, difficulty.hard, difficulty.sentences
+        );
+    }
 
     Ok(())
 }
+
+pub fn load_metadata(output_dir: &Path) -> io::Result<Metadata> {
+    let metadata_path = output_dir.join("metadata.json");
+    let metadata_json = fs::read_to_string(&metadata_path).map_err(|e| {
+        io::Error::new(e.kind(), format!("Cannot read {}: {}", metadata_path.display(), e))
+    })?;
+    Ok(serde_json::from_str(&metadata_json)?)
+}
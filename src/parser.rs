@@ -1,107 +1,383 @@
-use regex::Regex;
 use std::fs;
-use std::io::{self};
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::language::{self, Language};
+use crate::timestamp::Timestamp;
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+mod ass;
+mod clean;
+mod encoding;
+mod rolling;
+mod segmenter;
+mod srt;
+mod unpunctuated;
+mod vtt;
+
+pub use encoding::{encoding_for_label, Decoding};
+pub use srt::{Diagnostic, Severity};
+pub use unpunctuated::is_unpunctuated;
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
 pub struct Subtitle {
-    pub timestamp: String,
+    // 以前の sentences.json は開始時刻のみを "timestamp" として保存していた
+    #[serde(alias = "timestamp")]
+    pub start: Timestamp,
+    #[serde(default)]
+    pub end: Timestamp,
     pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
 }
 
-pub fn process_srt_file(input_path: &Path, output_dir: &Path) -> io::Result<PathBuf> {
-    let output_path = output_dir.join("sentences.json");
-    let srt_content = fs::read_to_string(input_path)?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+    Ass,
+}
 
-    let re = Regex::new(r"\d+\r?\n(\d{2}:\d{2}:\d{2},\d{3}) --> .*\r?\n([\s\S]+?)(?:\r?\n\r?\n|\z)").unwrap();
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    // ASS/SSA の Style 名と Name（話者）で取り込むイベントを絞り込む。空なら全て
+    pub ass_styles: Vec<String>,
+    pub ass_names: Vec<String>,
+    // YouTube自動字幕のようにキュー間で行が繰り返される字幕の重複を取り除く
+    pub dedupe_rolling: bool,
+    // タグ・話者ラベル・注釈を取り除くクリーニング。keep_annotations なら [Music] などの注釈は残す
+    pub clean_text: bool,
+    pub keep_annotations: bool,
+    // 句読点のない字幕では、この長さ以上の無音区間と最大語数で文を区切る
+    pub pause_gap: Duration,
+    pub max_sentence_words: usize,
+    // 文字コードの明示指定。None ならBOMと内容から自動判定する
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    // SRTの検証でエラーが見つかったら読み込みを中止する（load_cues）。
+    // parse_subtitles と parse_subtitles_from_reader は中止せず、診断を返すので呼び出し側で判断する
+    pub strict: bool,
+    // 字幕の言語。文の区切り方と、キューを結合するときに空白を入れるかどうかが変わる
+    pub language: &'static Language,
+}
 
-    let mut subtitles = Vec::new();
-    let mut current_sentence_parts: Vec<(String, String)> = Vec::new(); // (text_part, start_time)
-    
-    for cap in re.captures_iter(&srt_content) {
-        let block_start_time = cap[1].to_string();
-        let block_text = cap[2].replace("\r\n", " ").replace('\n', " ").trim().to_string();
-
-        // 現在のブロックのテキストとタイムスタンプをパーツとして追加
-        current_sentence_parts.push((block_text, block_start_time));
-
-        // 現在のブロックのテキストが文の区切りで終わるか
-        if current_sentence_parts.last().map_or(false, |(text, _)| text.ends_with('.') || text.ends_with('?') || text.ends_with('!')) {
-            let combined_text: String = current_sentence_parts.iter().map(|(text, _)| text.clone()).collect::<Vec<String>>().join(" ");
-            
-            let sentences: Vec<&str> = combined_text.split_inclusive(&['.', '?', '!'][..]).collect();
-            
-            let mut current_char_index = 0;
-            for sentence_slice in sentences {
-                let trimmed_sentence = sentence_slice.trim();
-                if !trimmed_sentence.is_empty() {
-                    // この文の開始タイムスタンプを特定する
-                    let mut sentence_start_time = String::new();
-                    let mut found_start_time = false;
-
-                    let mut temp_char_count = 0;
-                    for (part_text, part_time) in &current_sentence_parts {
-                        if current_char_index >= temp_char_count && current_char_index < temp_char_count + part_text.len() {
-                            sentence_start_time = part_time.clone();
-                            found_start_time = true;
-                            break;
-                        }
-                        temp_char_count += part_text.len() + 1; // +1 for space added when joining
-                    }
-                    // もし見つからなければ、最初のブロックのタイムスタンプを使う（フォールバック）
-                    if !found_start_time && !current_sentence_parts.is_empty() {
-                        sentence_start_time = current_sentence_parts[0].1.clone();
-                    }
-
-
-                    subtitles.push(Subtitle {
-                        timestamp: sentence_start_time,
-                        text: trimmed_sentence.to_string(),
-                    });
-                }
-                current_char_index += sentence_slice.len(); // 次の文の開始位置を更新
-            }
-            current_sentence_parts.clear(); // 処理が終わったのでクリア
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            ass_styles: Vec::new(),
+            ass_names: Vec::new(),
+            dedupe_rolling: false,
+            clean_text: true,
+            keep_annotations: false,
+            pause_gap: Duration::from_millis(800),
+            max_sentence_words: 30,
+            encoding: None,
+            strict: false,
+            language: language::ENGLISH,
+        }
+    }
+}
+
+impl SubtitleFormat {
+    // 拡張子で判定し、分からなければ内容の先頭から推測する
+    pub fn detect(path: &Path, content: &str) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_ascii_lowercase()).as_deref() {
+            Some("vtt") => SubtitleFormat::WebVtt,
+            Some("srt") => SubtitleFormat::Srt,
+            Some("ass") | Some("ssa") => SubtitleFormat::Ass,
+            _ => Self::sniff(content),
         }
     }
 
-    // ループの最後に残ったテキストを処理 (同様のロジックを適用)
-    if !current_sentence_parts.is_empty() {
-        let combined_text: String = current_sentence_parts.iter().map(|(text, _)| text.clone()).collect::<Vec<String>>().join(" ");
-        let sentences: Vec<&str> = combined_text.split_inclusive(&['.', '?', '!'][..]).collect();
-        
-        let mut current_char_index = 0;
-        for sentence_slice in sentences {
-            let trimmed_sentence = sentence_slice.trim();
-            if !trimmed_sentence.is_empty() {
-                let mut sentence_start_time = String::new();
-                let mut found_start_time = false;
-
-                let mut temp_char_count = 0;
-                for (part_text, part_time) in &current_sentence_parts {
-                    if current_char_index >= temp_char_count && current_char_index < temp_char_count + part_text.len() {
-                        sentence_start_time = part_time.clone();
-                        found_start_time = true;
-                        break;
-                    }
-                    temp_char_count += part_text.len() + 1;
-                }
-                if !found_start_time && !current_sentence_parts.is_empty() {
-                    sentence_start_time = current_sentence_parts[0].1.clone();
-                }
-
-                subtitles.push(Subtitle {
-                    timestamp: sentence_start_time,
-                    text: trimmed_sentence.to_string(),
-                });
-            }
-            current_char_index += sentence_slice.len();
+    pub fn sniff(content: &str) -> Self {
+        let content = content.trim_start_matches('\u{feff}');
+        if content.starts_with("WEBVTT") {
+            SubtitleFormat::WebVtt
+        } else if content.trim_start().starts_with("[Script Info]") {
+            SubtitleFormat::Ass
+        } else {
+            SubtitleFormat::Srt
         }
     }
+}
+
+// SRTは行番号付きの診断を返す。WebVTTとASSは読み取れない部分を黙って読み飛ばす
+pub fn parse_cues(content: &str, format: SubtitleFormat, options: &ParseOptions) -> (Vec<Subtitle>, Vec<Diagnostic>) {
+    match format {
+        SubtitleFormat::Srt => srt::parse(content),
+        SubtitleFormat::WebVtt => (vtt::parse(content), Vec::new()),
+        SubtitleFormat::Ass => (ass::parse(content, options), Vec::new()),
+    }
+}
+
+// ブロックを結合してから文に分割し、各文の最初と最後の文字を含むブロックから開始・終了タイムスタンプを割り当てる
+fn merge_by_punctuation(parts: &[&Subtitle], language: &Language, subtitles: &mut Vec<Subtitle>) {
+    let mut combined_text = String::new();
+    let mut part_offsets = Vec::with_capacity(parts.len()); // 結合後の各ブロックの開始位置
+    for part in parts {
+        if !combined_text.is_empty() && !language.without_spaces {
+            combined_text.push(' ');
+        }
+        part_offsets.push(combined_text.len());
+        combined_text.push_str(&part.text);
+    }
+
+    let part_at = |char_index: usize| parts[part_offsets.partition_point(|&offset| offset <= char_index) - 1];
+
+    for range in segmenter::split_sentences(&combined_text, language) {
+        let first_part = part_at(range.start);
+        subtitles.push(Subtitle {
+            start: first_part.start,
+            end: part_at(range.end - 1).end,
+            text: combined_text[range].to_string(),
+            speaker: first_part.speaker.clone(),
+        });
+    }
+}
+
+// 話者が切り替わったところでは必ず文を区切る。話者のないブロックは直前の話者の続きとみなす
+fn merge_by_speaker_turns(cues: &[Subtitle], language: &Language) -> Vec<Subtitle> {
+    let mut subtitles = Vec::new();
+    let mut turn: Vec<&Subtitle> = Vec::new();
+
+    for cue in cues.iter().filter(|cue| !cue.text.is_empty()) {
+        let turn_speaker = turn.iter().find_map(|part| part.speaker.as_deref());
+        if let (Some(current), Some(next)) = (turn_speaker, cue.speaker.as_deref())
+            && current != next
+        {
+            merge_by_punctuation(&turn, language, &mut subtitles);
+            turn.clear();
+        }
+        turn.push(cue);
+    }
+    merge_by_punctuation(&turn, language, &mut subtitles);
+
+    subtitles
+}
+
+pub fn merge_into_sentences(cues: &[Subtitle], options: &ParseOptions) -> Vec<Subtitle> {
+    if is_unpunctuated(cues, options.language) {
+        unpunctuated::split_by_pauses(cues, options.pause_gap, options.max_sentence_words, options.language)
+    } else {
+        merge_by_speaker_turns(cues, options.language)
+    }
+}
+
+// 読み込み・クリーニング・重複除去までを行い、文に結合する前のキュー単位の字幕と診断を返す
+pub fn prepare_cues(content: &str, format: SubtitleFormat, options: &ParseOptions) -> (Vec<Subtitle>, Vec<Diagnostic>) {
+    let (mut cues, diagnostics) = parse_cues(content, format, options);
+    cues = if options.clean_text {
+        clean::clean_cues(cues, options.keep_annotations, options.language)
+    } else {
+        clean::join_lines(cues)
+    };
+    if options.dedupe_rolling {
+        cues = rolling::dedupe_rolling_captions(cues);
+    }
+    (cues, diagnostics)
+}
+
+// 字幕テキストを文単位の Subtitle に変換し、SRTの診断と一緒に返す。ファイルの読み書きは行わない
+pub fn parse_subtitles(content: &str, format: SubtitleFormat, options: &ParseOptions) -> (Vec<Subtitle>, Vec<Diagnostic>) {
+    let (cues, diagnostics) = prepare_cues(content, format, options);
+    (merge_into_sentences(&cues, options), diagnostics)
+}
+
+// 任意のリーダーから字幕を読み、文字コードと形式（format が None の場合）を判定して文単位の Subtitle に変換する。
+// 判定した文字コードも一緒に返し、何も表示しない
+pub fn parse_subtitles_from_reader<R: Read>(
+    mut reader: R,
+    format: Option<SubtitleFormat>,
+    options: &ParseOptions,
+) -> io::Result<(Vec<Subtitle>, Vec<Diagnostic>, Decoding)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let (content, decoding) = encoding::decode_subtitle_bytes(&bytes, options.encoding);
+    let format = format.unwrap_or_else(|| SubtitleFormat::sniff(&content));
+    let (subtitles, diagnostics) = parse_subtitles(&content, format, options);
+    Ok((subtitles, diagnostics, decoding))
+}
+
+pub fn read_subtitle_file(input_path: &Path, encoding: Option<&'static encoding_rs::Encoding>) -> io::Result<(String, SubtitleFormat, Decoding)> {
+    let bytes = fs::read(input_path)?;
+    let (content, decoding) = encoding::decode_subtitle_bytes(&bytes, encoding);
+    let format = SubtitleFormat::detect(input_path, &content);
+    Ok((content, format, decoding))
+}
+
+// UTF-8以外で読んだことと、デコードできないバイトがあったことを表示する（load_cues と validate コマンド用）
+pub fn report_decoding(decoding: &Decoding) {
+    if decoding.encoding != encoding_rs::UTF_8 {
+        println!("Decoding subtitle file as {}.", decoding.encoding.name());
+    }
+    if decoding.had_errors {
+        eprintln!("Warning: some bytes could not be decoded as {} and were replaced. Try --encoding to override.", decoding.encoding.name());
+    }
+}
+
+// 字幕ファイルを読み込み、文字コードと診断を表示して、文に結合する前のキュー単位の字幕を返す
+pub fn load_cues(input_path: &Path, options: &ParseOptions) -> io::Result<Vec<Subtitle>> {
+    let (content, format, decoding) = read_subtitle_file(input_path, options.encoding)?;
+    report_decoding(&decoding);
+
+    let (cues, diagnostics) = prepare_cues(&content, format, options);
+    for diagnostic in &diagnostics {
+        eprintln!("{}: {}", input_path.display(), diagnostic);
+    }
+    let error_count = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    if options.strict && error_count > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has {} error(s). Fix them or run without --strict to recover.", input_path.display(), error_count),
+        ));
+    }
+
+    Ok(cues)
+}
+
+pub fn write_sentences_json(sentences: &[Subtitle], output_dir: &Path) -> io::Result<PathBuf> {
+    let output_path = output_dir.join("sentences.json");
 
-    let json_output = serde_json::to_string_pretty(&subtitles)?;
+    let json_output = serde_json::to_string_pretty(sentences)?;
     fs::write(&output_path, json_output)?;
 
     println!("Successfully created sentences file at {}", output_path.display());
     Ok(output_path)
-}
\ No newline at end of file
+}
+
+pub fn read_sentences_json(output_dir: &Path) -> io::Result<Vec<Subtitle>> {
+    let input_path = output_dir.join("sentences.json");
+    let json_input = fs::read_to_string(&input_path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Cannot read {}: {}", input_path.display(), e)))?;
+    Ok(serde_json::from_str(&json_input)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(sentences: &[Subtitle]) -> Vec<&str> {
+        sentences.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_srt_text_into_sentences() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nMr. Smith is here. He\n\n2\n00:00:02,500 --> 00:00:04,000\n<i>wants to talk.</i>\n";
+        let (sentences, _) = parse_subtitles(srt, SubtitleFormat::Srt, &ParseOptions::default());
+
+        assert_eq!(texts(&sentences), ["Mr. Smith is here.", "He wants to talk."]);
+        assert_eq!(sentences[1].start.to_string(), "00:00:01,000");
+        assert_eq!(sentences[1].end.to_string(), "00:00:04,000");
+    }
+
+    #[test]
+    fn returns_srt_diagnostics_with_the_sentences() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nFirst one.\n\n2\nnot a timing line\nBroken timing.\n";
+        let (sentences, diagnostics) = parse_subtitles(srt, SubtitleFormat::Srt, &ParseOptions { strict: true, ..ParseOptions::default() });
+
+        assert_eq!(texts(&sentences), ["First one."]);
+        assert!(diagnostics.iter().any(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn parses_webvtt_from_reader_with_format_sniffing() {
+        let vtt = "WEBVTT\n\nNOTE ignored\n\ncue-1\n00:01.000 --> 00:02.500 align:start\n<v Anna>Hello there.\n";
+        let (sentences, _, _) = parse_subtitles_from_reader(vtt.as_bytes(), None, &ParseOptions::default()).unwrap();
+
+        assert_eq!(texts(&sentences), ["Hello there."]);
+        assert_eq!(sentences[0].speaker.as_deref(), Some("Anna"));
+        assert_eq!(sentences[0].end.to_string(), "00:00:02,500");
+    }
+
+    #[test]
+    fn starts_a_webvtt_cue_at_each_timing_line() {
+        let vtt = "WEBVTT\n\n00:01.000 --> 00:02.000\nFirst one.\n  \n00:03.000 --> 00:04.000\nSecond one.\n00:05.000 --> 00:06.000\nThird one.\n";
+        let (sentences, _) = parse_subtitles(vtt, SubtitleFormat::WebVtt, &ParseOptions::default());
+
+        assert_eq!(texts(&sentences), ["First one.", "Second one.", "Third one."]);
+        assert_eq!(sentences[2].start.to_string(), "00:00:05,000");
+    }
+
+    #[test]
+    fn skips_cues_whose_hours_overflow() {
+        let vtt = "WEBVTT\n\n99999999999999:00:00.000 --> 99999999999999:00:01.000\nToo late.\n\n00:01.000 --> 00:02.000\nIn time.\n";
+        let ass = format!(
+            "{ASS_HEADER}Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,99999999999999:00:00.00,99999999999999:00:01.00,Default,,0,0,0,,Too late.\n\
+             Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,In time.\n"
+        );
+
+        assert_eq!(texts(&parse_subtitles(vtt, SubtitleFormat::WebVtt, &ParseOptions::default()).0), ["In time."]);
+        assert_eq!(texts(&parse_subtitles(&ass, SubtitleFormat::Ass, &ParseOptions::default()).0), ["In time."]);
+    }
+
+    const ASS_HEADER: &str = "[Script Info]\nScriptType: v4.00+\n\n[Events]\n";
+
+    #[test]
+    fn reads_ass_fields_in_format_order() {
+        let ass = format!(
+            "{ASS_HEADER}Format: Layer, Style, Name, Start, End, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,Default,Anna,0:00:03.05,0:00:04.00,0,0,0,,Later line.\n\
+             Dialogue: 0,Default,Ben,0:00:01.50,0:00:02.07,0,0,0,,Well, hello, there.\n"
+        );
+        let (cues, _) = parse_cues(&ass, SubtitleFormat::Ass, &ParseOptions::default());
+
+        assert_eq!(texts(&cues), ["Well, hello, there.", "Later line."]);
+        assert_eq!(cues[0].start.to_string(), "00:00:01,500");
+        assert_eq!(cues[0].end.to_string(), "00:00:02,070");
+        assert_eq!(cues[0].speaker.as_deref(), Some("Ben"));
+    }
+
+    #[test]
+    fn strips_ass_override_tags_and_drawings() {
+        let ass = format!(
+            "{ASS_HEADER}Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{{\\i1}}Hi{{\\i0}}\\Nthere\\hfriend {{\\p1}}m 0 0 l 100 0{{\\p0}}done\n"
+        );
+        let (cues, _) = parse_cues(&ass, SubtitleFormat::Ass, &ParseOptions::default());
+
+        assert_eq!(texts(&cues), ["Hi\nthere friend done"]);
+        assert_eq!(cues[0].speaker, None);
+    }
+
+    #[test]
+    fn filters_ass_events_by_style_and_name() {
+        let ass = format!(
+            "{ASS_HEADER}Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             Dialogue: 0,0:00:01.00,0:00:02.00,Default,Anna,0,0,0,,From Anna.\n\
+             Dialogue: 0,0:00:01.00,0:00:02.00,Sign,,0,0,0,,A street sign.\n\
+             Dialogue: 0,0:00:03.00,0:00:04.00,Default,Ben,0,0,0,,From Ben.\n"
+        );
+        let by_style = ParseOptions { ass_styles: vec!["default".to_string()], ..ParseOptions::default() };
+        let by_name = ParseOptions { ass_names: vec!["Ben".to_string()], ..ParseOptions::default() };
+
+        assert_eq!(texts(&parse_cues(&ass, SubtitleFormat::Ass, &by_style).0), ["From Anna.", "From Ben."]);
+        assert_eq!(texts(&parse_cues(&ass, SubtitleFormat::Ass, &by_name).0), ["From Ben."]);
+    }
+
+    #[test]
+    fn splits_unpunctuated_japanese_by_pauses_without_inserting_spaces() {
+        let srt = "1\n00:00:01,000 --> 00:00:03,000\n今日はとても良い天気なので公園まで歩いて\n\n\
+                   2\n00:00:03,000 --> 00:00:05,000\n行こうと思っていたけれど雨が降ってきたから\n\n\
+                   3\n00:00:07,000 --> 00:00:09,000\n家で本を読みながらゆっくり過ごすことにした\n\n\
+                   4\n00:00:09,000 --> 00:00:11,000\n明日は晴れるといいなと思いながら寝てしまった\n";
+        let options = ParseOptions { language: language::JAPANESE, ..ParseOptions::default() };
+        let (sentences, _) = parse_subtitles(srt, SubtitleFormat::Srt, &options);
+
+        assert_eq!(
+            texts(&sentences),
+            ["今日はとても良い天気なので公園まで歩いて行こうと思っていたけれど雨が降ってきたから", "家で本を読みながらゆっくり過ごすことにした明日は晴れるといいなと思いながら寝てしまった"]
+        );
+        assert_eq!(sentences[1].start.to_string(), "00:00:07,000");
+    }
+
+    #[test]
+    fn decodes_utf16_input_from_reader() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nCafé time.\n";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(srt.encode_utf16().flat_map(u16::to_le_bytes));
+        let (sentences, _, decoding) = parse_subtitles_from_reader(bytes.as_slice(), None, &ParseOptions::default()).unwrap();
+
+        assert_eq!(texts(&sentences), ["Café time."]);
+        assert_eq!(decoding.encoding, encoding_rs::UTF_16LE);
+        assert!(!decoding.had_errors);
+    }
+}
@@ -0,0 +1,115 @@
+use super::{ParseOptions, Subtitle};
+use crate::timestamp::Timestamp;
+
+// ASSのタイムスタンプは H:MM:SS.cc（センチ秒）
+fn parse_ass_timestamp(ass_time: &str) -> Option<Timestamp> {
+    let (clock, centis) = ass_time.trim().split_once('.')?;
+    let parts: Vec<&str> = clock.split(':').collect();
+    let [h, m, s] = parts.as_slice() else {
+        return None;
+    };
+    let h: u64 = h.parse().ok()?;
+    let m: u64 = m.parse().ok()?;
+    let s: u64 = s.parse().ok()?;
+    let cs: u64 = centis.parse().ok()?;
+    Timestamp::from_hms_millis(h, m, s, cs.checked_mul(10)?)
+}
+
+// {\...} のオーバーライドタグを取り除き、描画コマンド（\p1 ～ \p0）も読み飛ばす。\N, \n は改行、\h は空白にする
+fn strip_override_tags(text: &str) -> String {
+    let mut result = String::new();
+    let mut in_drawing = false;
+    let mut rest = text;
+
+    while let Some(open) = rest.find('{') {
+        if !in_drawing {
+            result.push_str(&rest[..open]);
+        }
+        let Some(close) = rest[open..].find('}') else {
+            rest = &rest[open..];
+            break;
+        };
+        let tag = &rest[open + 1..open + close];
+        for command in tag.split('\\') {
+            if let Some(level) = command.strip_prefix('p')
+                && let Ok(level) = level.trim().parse::<u32>()
+            {
+                in_drawing = level > 0;
+            }
+        }
+        rest = &rest[open + close + 1..];
+    }
+    if !in_drawing {
+        result.push_str(rest);
+    }
+
+    result
+        .replace("\\N", "\n")
+        .replace("\\n", "\n")
+        .replace("\\h", " ")
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn matches_filter(value: &str, filter: &[String]) -> bool {
+    filter.is_empty() || filter.iter().any(|f| f.eq_ignore_ascii_case(value.trim()))
+}
+
+pub fn parse(content: &str, options: &ParseOptions) -> Vec<Subtitle> {
+    let mut in_events = false;
+    // [Events] の Format 行がなければ ASS の標準的なフィールド順を仮定する
+    let mut fields: Vec<String> = ["layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let mut events: Vec<Subtitle> = Vec::new();
+
+    for line in content.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "Format" => {
+                fields = value.split(',').map(|f| f.trim().to_ascii_lowercase()).collect();
+            }
+            "Dialogue" => {
+                // Text は最後のフィールドで、本文中にカンマを含みうる
+                let values: Vec<&str> = value.trim_start().splitn(fields.len(), ',').collect();
+                if values.len() != fields.len() {
+                    continue;
+                }
+                let field = |name: &str| fields.iter().position(|f| f == name).map(|i| values[i]).unwrap_or("");
+
+                if !matches_filter(field("style"), &options.ass_styles) || !matches_filter(field("name"), &options.ass_names) {
+                    continue;
+                }
+                let (Some(start), Some(end)) = (parse_ass_timestamp(field("start")), parse_ass_timestamp(field("end"))) else {
+                    continue;
+                };
+                let text = strip_override_tags(field("text"));
+                if text.is_empty() {
+                    continue;
+                }
+                let speaker = Some(field("name").trim()).filter(|name| !name.is_empty()).map(str::to_string);
+                events.push(Subtitle { start, end, text, speaker });
+            }
+            _ => {}
+        }
+    }
+
+    // ASS のイベントは時間順に並んでいるとは限らない
+    events.sort_by_key(|event| event.start);
+    events
+}
@@ -0,0 +1,125 @@
+use regex::Regex;
+use super::Subtitle;
+use crate::language::Language;
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// キュー内の改行を空白にして1行にまとめる（クリーニングを無効にした場合）
+pub fn join_lines(cues: Vec<Subtitle>) -> Vec<Subtitle> {
+    cues.into_iter()
+        .map(|cue| Subtitle {
+            text: collapse_whitespace(&cue.text),
+            ..cue
+        })
+        .filter(|cue| !cue.text.is_empty())
+        .collect()
+}
+
+// 括弧書きのうち、"(laughs)" や "(SIGHS)" のような短い効果音・動作の注釈だけを注釈とみなす。
+// "(with my sister)" のような台詞の一部は残す
+fn is_sound_cue(inner: &str) -> bool {
+    let words: Vec<&str> = inner.split_whitespace().collect();
+    let letters = || inner.chars().filter(|c| c.is_alphabetic());
+    if words.is_empty() || letters().next().is_none() {
+        return false;
+    }
+    let all_caps = letters().count() >= 2 && letters().all(char::is_uppercase);
+    let lowercase = words.len() <= 2 && letters().all(char::is_lowercase);
+    all_caps || lowercase
+}
+
+// <i>, <font ...> などのタグと {\an8} のような位置指定を除去し、"JOHN:" のような話者ラベルを speaker に移す。
+// [Music] や (laughs) のような注釈は keep_annotations が false のとき取り除く。括弧書きは行全体を占めるか、短い効果音・動作の注釈のときだけ取り除く。
+// 1つのキューに複数の話者の行がある場合は、話者ごとに同じ時刻のキューに分ける。
+pub fn clean_cues(cues: Vec<Subtitle>, keep_annotations: bool, language: &Language) -> Vec<Subtitle> {
+    let markup_re = Regex::new(r"</?[A-Za-z][^>]*>|\{\\[^}]*\}").unwrap();
+    let annotation_re = Regex::new(r"\[[^\]]*\]|♪[^♪]*(?:♪|$)|[♪♫]").unwrap();
+    let parenthetical_re = Regex::new(r"\(([^)]*)\)").unwrap();
+    let whole_line_parenthetical_re = Regex::new(r"^(?:>>+|[-‐–—])?\s*\([^)]*\)$").unwrap();
+    let dash_re = Regex::new(r"^(?:>>+|[-‐–—])\s*").unwrap();
+    let speaker_re = Regex::new(r"^([A-Z][A-Z0-9'.\- ]{0,30}[A-Z0-9.]|[A-Z])\s*:\s+").unwrap();
+
+    let mut cleaned = Vec::with_capacity(cues.len());
+
+    for cue in cues {
+        let mut segments: Vec<(Option<String>, Vec<String>)> = vec![(cue.speaker.clone(), Vec::new())];
+
+        for line in cue.text.lines() {
+            let mut line = markup_re.replace_all(line, "").to_string();
+            if !keep_annotations {
+                line = annotation_re.replace_all(&line, "").to_string();
+                if whole_line_parenthetical_re.is_match(line.trim()) {
+                    line.clear();
+                }
+                line = parenthetical_re
+                    .replace_all(&line, |cap: &regex::Captures| if is_sound_cue(&cap[1]) { String::new() } else { cap[0].to_string() })
+                    .to_string();
+            }
+            let line = line.trim();
+            let line = dash_re.replace(line, "");
+            let (speaker, line) = match speaker_re.captures(&line) {
+                Some(cap) => (Some(cap[1].trim().to_string()), line[cap[0].len()..].to_string()),
+                None => (None, line.to_string()),
+            };
+            let line = collapse_whitespace(&line);
+            if line.is_empty() {
+                continue;
+            }
+
+            let current = segments.last_mut().unwrap();
+            match speaker {
+                Some(speaker) if current.1.is_empty() => current.0 = Some(speaker),
+                Some(speaker) if current.0.as_deref() != Some(speaker.as_str()) => {
+                    segments.push((Some(speaker), vec![line]));
+                    continue;
+                }
+                _ => {}
+            }
+            segments.last_mut().unwrap().1.push(line);
+        }
+
+        for (speaker, lines) in segments {
+            if lines.is_empty() {
+                continue;
+            }
+            cleaned.push(Subtitle {
+                start: cue.start,
+                end: cue.end,
+                text: lines.join(if language.without_spaces { "" } else { " " }),
+                speaker,
+            });
+        }
+    }
+
+    cleaned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clean_cues;
+    use crate::language::ENGLISH;
+    use crate::parser::Subtitle;
+
+    fn clean(text: &str) -> Vec<(Option<String>, String)> {
+        let cue = Subtitle { text: text.to_string(), ..Subtitle::default() };
+        clean_cues(vec![cue], false, ENGLISH).into_iter().map(|cue| (cue.speaker, cue.text)).collect()
+    }
+
+    #[test]
+    fn removes_annotations_but_keeps_parenthetical_dialogue() {
+        assert_eq!(clean("[Music] (laughs) Hello (SIGHS) there."), [(None, "Hello there.".to_string())]);
+        assert_eq!(clean("I went (with my sister) to the store."), [(None, "I went (with my sister) to the store.".to_string())]);
+        assert_eq!(clean("(Someone knocks on the door)\n♪ la la ♪ Come in."), [(None, "Come in.".to_string())]);
+    }
+
+    #[test]
+    fn moves_speaker_labels_and_splits_dashed_turns() {
+        assert_eq!(
+            clean("<i>- JOHN: Are you coming?</i>\n- MARY: Not yet."),
+            [(Some("JOHN".to_string()), "Are you coming?".to_string()), (Some("MARY".to_string()), "Not yet.".to_string())]
+        );
+        assert_eq!(clean(">> Welcome back.\n{\\an8}Glad to be here."), [(None, "Welcome back. Glad to be here.".to_string())]);
+    }
+}
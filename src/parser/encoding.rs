@@ -0,0 +1,64 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use std::io;
+
+pub fn encoding_for_label(label: &str) -> io::Result<&'static Encoding> {
+    // WHATWGのラベルに加えて、よく使われる別名も受け付ける
+    let normalized = match label.trim().to_ascii_lowercase().as_str() {
+        "utf16" | "utf-16" => "utf-16le".to_string(),
+        "sjis" | "cp932" | "windows-31j" => "shift_jis".to_string(),
+        "latin-1" | "latin1" => "iso-8859-1".to_string(),
+        other => other.to_string(),
+    };
+    Encoding::for_label(normalized.as_bytes())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown encoding: {}", label)))
+}
+
+// BOMのないUTF-16は、ASCII文字の上位バイトが0になることから偶数・奇数位置の0バイトの偏りで判定する
+fn sniff_utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(4096) & !1];
+    if sample.len() < 16 {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+
+    if odd_zeros * 10 > pairs * 4 && even_zeros * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 > pairs * 4 && odd_zeros * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some(encoding) = sniff_utf16_without_bom(bytes) {
+        return encoding;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+// デコードに使った文字コードと、デコードできずに置き換えたバイトがあったかどうか
+#[derive(Debug, Clone, Copy)]
+pub struct Decoding {
+    pub encoding: &'static Encoding,
+    pub had_errors: bool,
+}
+
+// BOMがあればそれに従い、なければ encoding の指定、自動判定の順で文字コードを決めてデコードする。表示は呼び出し側で行う
+pub fn decode_subtitle_bytes(bytes: &[u8], encoding: Option<&'static Encoding>) -> (String, Decoding) {
+    let (encoding, body) = match (Encoding::for_bom(bytes), encoding) {
+        (Some((bom_encoding, bom_length)), _) => (bom_encoding, &bytes[bom_length..]),
+        (None, Some(forced)) => (forced, bytes),
+        (None, None) => (detect_encoding(bytes), bytes),
+    };
+    let (text, had_errors) = encoding.decode_without_bom_handling(body);
+    (text.into_owned(), Decoding { encoding, had_errors })
+}
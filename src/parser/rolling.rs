@@ -0,0 +1,46 @@
+use super::Subtitle;
+
+// 大文字小文字と前後の記号を無視して単語を比較する
+fn normalize_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+// 直前のキューの末尾と現在のキューの先頭が一致する最長の単語数
+fn overlap_len(previous: &[String], current: &[String]) -> usize {
+    let max = previous.len().min(current.len());
+    (1..=max)
+        .rev()
+        .find(|&k| previous[previous.len() - k..] == current[..k])
+        .unwrap_or(0)
+}
+
+// YouTubeの自動字幕は2行のキューが1行ずつスクロールするため、前のキューの行が次のキューの先頭に繰り返される。
+// 重複した単語を後ろのキューから取り除き、各単語は最初に現れたキュー（最も早いタイムスタンプ）にだけ残す。
+pub fn dedupe_rolling_captions(cues: Vec<Subtitle>) -> Vec<Subtitle> {
+    let mut deduped: Vec<Subtitle> = Vec::with_capacity(cues.len());
+    let mut previous_words: Vec<String> = Vec::new();
+
+    for mut cue in cues {
+        let words: Vec<&str> = cue.text.split_whitespace().collect();
+        let normalized: Vec<String> = words.iter().map(|word| normalize_word(word)).collect();
+        let overlap = overlap_len(&previous_words, &normalized);
+
+        if overlap == words.len() {
+            // 新しい単語がない（同じ行を表示し続けているだけの）キューは、前のキューの表示時間を延ばす
+            if let Some(last) = deduped.last_mut()
+                && cue.end > last.end
+            {
+                last.end = cue.end;
+            }
+        } else {
+            cue.text = words[overlap..].join(" ");
+            deduped.push(cue);
+        }
+
+        if !normalized.is_empty() {
+            previous_words = normalized;
+        }
+    }
+
+    deduped
+}
@@ -0,0 +1,257 @@
+use std::ops::Range;
+use crate::language::Language;
+
+// 後ろに人名などが続くことがほぼ確実な略語。ここでは文を区切らない
+const TITLE_ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "mx", "dr", "prof", "st", "mt", "ft", "sen", "rep", "gov", "gen", "col", "capt", "cpt", "lt",
+    "sgt", "cmdr", "adm", "rev", "fr", "hon", "pres", "supt", "messrs", "mme", "mlle",
+];
+
+// 後ろに数字が続くときだけ略語として扱うもの（"No. 5", "vol. 2"）
+const NUMBER_ABBREVIATIONS: &[&str] = &["no", "nos", "vol", "vols", "p", "pp", "fig", "figs", "ch", "sec", "art", "op"];
+
+// 文末にも文中にも現れる略語。次の語が文頭らしい場合のみ区切る
+const ABBREVIATIONS: &[&str] = &[
+    "e.g", "i.e", "etc", "vs", "viz", "cf", "al", "approx", "appt", "apt", "dept", "est", "min", "max", "misc", "inc",
+    "ltd", "co", "corp", "bros", "jr", "sr", "esq", "ave", "blvd", "rd", "jan", "feb", "mar", "apr", "jun", "jul",
+    "aug", "sep", "sept", "oct", "nov", "dec", "mon", "tue", "tues", "wed", "thu", "thurs", "fri", "sat", "sun",
+    "a.m", "p.m", "u.s", "u.k", "u.n", "ph.d", "b.a", "m.a", "b.c", "a.d",
+];
+
+// 略語の後ろでこれらの語が大文字で始まっていれば、新しい文が始まったとみなす
+const SENTENCE_STARTERS: &[&str] = &[
+    "i", "you", "he", "she", "it", "we", "they", "the", "a", "an", "this", "that", "these", "those", "there", "here",
+    "but", "and", "so", "or", "yet", "then", "now", "well", "oh", "yes", "no", "okay", "ok", "what", "why", "how",
+    "when", "where", "who", "which", "if", "in", "on", "at", "after", "before", "my", "our", "your", "his", "her",
+    "their", "its", "let's", "let", "please", "thank", "thanks", "do", "does", "did", "is", "are", "was", "were",
+];
+
+const SPANISH_TITLE_ABBREVIATIONS: &[&str] = &["sr", "sra", "srta", "sres", "dr", "dra", "prof", "lic", "ing", "ud", "uds", "d", "dña", "sto", "sta"];
+const SPANISH_NUMBER_ABBREVIATIONS: &[&str] = &["núm", "no", "pág", "págs", "cap", "art", "vol"];
+const SPANISH_ABBREVIATIONS: &[&str] = &["etc", "p.ej", "aprox", "admón", "avda", "c", "cía", "dpto", "ej", "a.c", "d.c", "ee.uu"];
+const SPANISH_SENTENCE_STARTERS: &[&str] = &[
+    "yo", "tú", "él", "ella", "nosotros", "ellos", "ellas", "el", "la", "los", "las", "un", "una", "este", "esta", "eso",
+    "pero", "y", "entonces", "bueno", "sí", "no", "qué", "por", "cuando", "si", "en", "mi", "su", "hay", "es",
+];
+
+// 言語ごとの略語の一覧。一覧のない言語では略語を考慮しない
+struct Rules {
+    titles: &'static [&'static str],
+    numbers: &'static [&'static str],
+    abbreviations: &'static [&'static str],
+    starters: &'static [&'static str],
+}
+
+fn rules_for(language: &Language) -> Rules {
+    match language.code {
+        "en" => Rules {
+            titles: TITLE_ABBREVIATIONS,
+            numbers: NUMBER_ABBREVIATIONS,
+            abbreviations: ABBREVIATIONS,
+            starters: SENTENCE_STARTERS,
+        },
+        "es" => Rules {
+            titles: SPANISH_TITLE_ABBREVIATIONS,
+            numbers: SPANISH_NUMBER_ABBREVIATIONS,
+            abbreviations: SPANISH_ABBREVIATIONS,
+            starters: SPANISH_SENTENCE_STARTERS,
+        },
+        _ => Rules { titles: &[], numbers: &[], abbreviations: &[], starters: &[] },
+    }
+}
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '?' | '!' | '…') || is_full_width_terminator(c)
+}
+
+// 日本語・中国語の句点など。後ろに空白がなくても文末とする
+fn is_full_width_terminator(c: char) -> bool {
+    matches!(c, '。' | '？' | '！' | '｡')
+}
+
+fn is_closer(c: char) -> bool {
+    matches!(c, '"' | '\'' | '”' | '’' | ')' | ']' | '}' | '»' | '」' | '』' | '）')
+}
+
+fn is_opener(c: char) -> bool {
+    matches!(c, '"' | '\'' | '“' | '‘' | '(' | '[' | '{' | '«' | '¿' | '¡' | '「' | '『' | '（')
+}
+
+// "U.S." や "a.m." のような1文字ずつピリオドで区切られた略語
+fn is_dotted_acronym(token: &str) -> bool {
+    let with_dot = format!("{}.", token);
+    let chars: Vec<char> = with_dot.chars().collect();
+    chars.len() >= 4 && chars.chunks(2).all(|pair| pair.len() == 2 && pair[0].is_alphabetic() && pair[1] == '.')
+}
+
+fn is_boundary(rules: &Rules, token: &str, terminators: &str, next_word: Option<&str>) -> bool {
+    let Some(next_word) = next_word else {
+        return true;
+    };
+    let next_word = next_word.trim_start_matches(is_opener);
+    let Some(next_first) = next_word.chars().next() else {
+        return true;
+    };
+
+    // 小文字で続く場合は文の途中（"p.m. tomorrow", "\"Really?\" she asked."）
+    if next_first.is_lowercase() {
+        return false;
+    }
+    if terminators.contains('?') || terminators.contains('!') || terminators.contains('…') || terminators.chars().count() > 1 {
+        return true;
+    }
+
+    let lower = token.to_lowercase();
+    if rules.titles.contains(&lower.as_str()) {
+        return false;
+    }
+    if rules.numbers.contains(&lower.as_str()) && next_first.is_ascii_digit() {
+        return false;
+    }
+
+    // "J. K. Rowling" のようなイニシャル（英語の "I" と "A" は単語として文末に来うるので除く）
+    let mut token_chars = token.chars();
+    if let (Some(initial), None) = (token_chars.next(), token_chars.next())
+        && initial.is_uppercase()
+        && !rules.starters.is_empty()
+        && initial != 'I'
+        && initial != 'A'
+    {
+        return !next_first.is_uppercase();
+    }
+
+    if rules.abbreviations.contains(&lower.as_str()) || (!rules.abbreviations.is_empty() && is_dotted_acronym(token)) {
+        let next_lower = next_word
+            .trim_end_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        return rules.starters.contains(&next_lower.as_str());
+    }
+
+    true
+}
+
+fn push_trimmed(text: &str, range: Range<usize>, sentences: &mut Vec<Range<usize>>) {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    if start < end {
+        sentences.push(start..end);
+    }
+}
+
+// 文に分割し、前後の空白を除いた各文のバイト範囲を返す。略語の扱いは言語ごとに異なる
+pub fn split_sentences(text: &str, language: &Language) -> Vec<Range<usize>> {
+    let rules = rules_for(language);
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_at = |index: usize| chars.get(index).map_or(text.len(), |(byte, _)| *byte);
+
+    let mut sentences = Vec::new();
+    let mut sentence_start = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        if !is_terminator(chars[i].1) {
+            i += 1;
+            continue;
+        }
+
+        // "?!" や "..." のような連続した終端記号と、その後ろの閉じ引用符・括弧をまとめて文末とする
+        let run_start = i;
+        while i < chars.len() && is_terminator(chars[i].1) {
+            i += 1;
+        }
+        let run_end = i;
+        while i < chars.len() && is_closer(chars[i].1) {
+            i += 1;
+        }
+
+        // 空白が続かない記号は文末ではない（"3.5", "U.S.A", "google.com"）。全角の句点は空白がなくても文末
+        let full_width = chars[run_start..run_end].iter().any(|(_, c)| is_full_width_terminator(*c));
+        if !full_width && i < chars.len() && !chars[i].1.is_whitespace() {
+            continue;
+        }
+        // 「本当？」と聞いた。のように、閉じ括弧の直後に文が続く場合は区切らない
+        if full_width && i > run_end && i < chars.len() && !chars[i].1.is_whitespace() && !is_opener(chars[i].1) {
+            continue;
+        }
+
+        let boundary = byte_at(i);
+        let terminators = &text[byte_at(run_start)..byte_at(run_end)];
+        let token = text[sentence_start..byte_at(run_start)]
+            .rsplit(char::is_whitespace)
+            .next()
+            .unwrap_or("")
+            .trim_start_matches(is_opener);
+        let next_word = text[boundary..].split_whitespace().next();
+
+        if full_width || is_boundary(&rules, token, terminators, next_word) {
+            push_trimmed(text, sentence_start..boundary, &mut sentences);
+            sentence_start = boundary;
+        }
+    }
+
+    push_trimmed(text, sentence_start..text.len(), &mut sentences);
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_sentences;
+    use crate::language::{language_for_code, Language, ENGLISH};
+
+    fn split<'a>(text: &'a str, language: &Language) -> Vec<&'a str> {
+        split_sentences(text, language).into_iter().map(|range| &text[range]).collect()
+    }
+
+    #[test]
+    fn corpus() {
+        let corpus: &[(&str, &[&str])] = &[
+            ("Hello there. How are you?", &["Hello there.", "How are you?"]),
+            ("Wait! Stop! Are you serious?!", &["Wait!", "Stop!", "Are you serious?!"]),
+            ("Mr. Smith went to Washington.", &["Mr. Smith went to Washington."]),
+            ("Mrs. Brown and Dr. Jones are here. They arrived late.", &["Mrs. Brown and Dr. Jones are here.", "They arrived late."]),
+            ("Prices rose 3.5 percent last year. That was a lot.", &["Prices rose 3.5 percent last year.", "That was a lot."]),
+            ("It costs $4.99. Cheap!", &["It costs $4.99.", "Cheap!"]),
+            ("He moved to the U.S. in 2010.", &["He moved to the U.S. in 2010."]),
+            ("He moved to the U.S. He never came back.", &["He moved to the U.S.", "He never came back."]),
+            ("The U.S. Army was there.", &["The U.S. Army was there."]),
+            ("Bring fruit, e.g. apples or pears.", &["Bring fruit, e.g. apples or pears."]),
+            ("Bring fruit, i.e. Apples. Then leave.", &["Bring fruit, i.e. Apples.", "Then leave."]),
+            ("We need pens, paper, etc. And we need them now.", &["We need pens, paper, etc.", "And we need them now."]),
+            ("We need pens, paper, etc. for the exam.", &["We need pens, paper, etc. for the exam."]),
+            ("I was... thinking about it.", &["I was... thinking about it."]),
+            ("I was thinking... No. Forget it.", &["I was thinking...", "No.", "Forget it."]),
+            ("Well… That happened.", &["Well…", "That happened."]),
+            ("J. K. Rowling wrote it.", &["J. K. Rowling wrote it."]),
+            ("He said, \"Go away.\" Then he left.", &["He said, \"Go away.\"", "Then he left."]),
+            ("\"Really?\" she asked.", &["\"Really?\" she asked."]),
+            ("(This is an aside.) The rest follows.", &["(This is an aside.)", "The rest follows."]),
+            ("Turn to No. 5 now. No. Not that one.", &["Turn to No. 5 now.", "No.", "Not that one."]),
+            ("See you at 5 p.m. tomorrow.", &["See you at 5 p.m. tomorrow."]),
+            ("Visit google.com today. It's free.", &["Visit google.com today.", "It's free."]),
+            ("It was me, I. Then you.", &["It was me, I.", "Then you."]),
+            ("No punctuation at the end", &["No punctuation at the end"]),
+            ("  Leading and trailing spaces.  ", &["Leading and trailing spaces."]),
+            ("", &[]),
+        ];
+
+        for (input, expected) in corpus {
+            assert_eq!(&split(input, ENGLISH), expected, "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn other_languages() {
+        let corpus: &[(&str, &str, &[&str])] = &[
+            ("es", "¿Dónde está el Sr. García? No lo sé.", &["¿Dónde está el Sr. García?", "No lo sé."]),
+            ("es", "Compramos pan, leche, etc. Y luego volvimos.", &["Compramos pan, leche, etc.", "Y luego volvimos."]),
+            ("ja", "今日は晴れです。明日は雨かな？「本当？」と聞いた。", &["今日は晴れです。", "明日は雨かな？", "「本当？」と聞いた。"]),
+            ("ko", "안녕하세요. 만나서 반갑습니다!", &["안녕하세요.", "만나서 반갑습니다!"]),
+        ];
+
+        for (code, input, expected) in corpus {
+            let language = language_for_code(code).unwrap();
+            assert_eq!(&split(input, language), expected, "input: {:?}", input);
+        }
+    }
+}
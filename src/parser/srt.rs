@@ -0,0 +1,184 @@
+use regex::{Captures, Regex};
+use std::fmt;
+use super::Subtitle;
+use crate::timestamp::Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    // 読み込みは続けられるが、規格から外れているもの
+    Warning,
+    // そのままではキューが失われる、または時刻が壊れているもの
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "line {}: {}: {}", self.line, severity, self.message)
+    }
+}
+
+struct Tokenizer<'a> {
+    lines: Vec<&'a str>,
+    standard_timing_re: Regex,
+    loose_timing_re: Regex,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn report(&mut self, line_index: usize, severity: Severity, message: String) {
+        self.diagnostics.push(Diagnostic { line: line_index + 1, severity, message });
+    }
+
+    fn is_blank(&self, line_index: usize) -> bool {
+        self.lines.get(line_index).is_none_or(|line| line.trim().is_empty())
+    }
+
+    fn is_index(&self, line_index: usize) -> bool {
+        self.lines.get(line_index).is_some_and(|line| {
+            let line = line.trim();
+            !line.is_empty() && line.bytes().all(|b| b.is_ascii_digit())
+        })
+    }
+
+    fn is_timing(&self, line_index: usize) -> bool {
+        self.lines.get(line_index).is_some_and(|line| self.loose_timing_re.is_match(line))
+    }
+
+    // 規格外の書式（"." 区切りのミリ秒、1桁の時間、"->" の矢印など）も読み取る
+    fn loose_time(cap: &Captures, offset: usize) -> Option<Timestamp> {
+        let number = |i: usize| cap.get(offset + i).map_or(Some(0), |m| m.as_str().parse::<u64>().ok());
+        let (h, m, s) = (number(1)?, number(2)?, number(3)?);
+        let millis = cap.get(offset + 4).map_or(Some(0), |m| format!("{:0<3}", m.as_str()).parse::<u64>().ok())?;
+        if m >= 60 || s >= 60 {
+            return None;
+        }
+        Timestamp::from_hms_millis(h, m, s, millis)
+    }
+
+    fn parse_timing(&mut self, line_index: usize) -> Option<(Timestamp, Timestamp)> {
+        let line = self.lines[line_index];
+        if let Some(cap) = self.standard_timing_re.captures(line) {
+            return Some((Timestamp::parse_srt(&cap[1]).ok()?, Timestamp::parse_srt(&cap[2]).ok()?));
+        }
+
+        let cap = self.loose_timing_re.captures(line)?;
+        let times = (Self::loose_time(&cap, 0)?, Self::loose_time(&cap, 4)?);
+        self.report(line_index, Severity::Warning, format!("non-standard timing line (expected 'HH:MM:SS,mmm --> HH:MM:SS,mmm'): '{}'", line.trim()));
+        Some(times)
+    }
+
+    fn tokenize(mut self) -> (Vec<Subtitle>, Vec<Diagnostic>) {
+        let mut cues: Vec<Subtitle> = Vec::new();
+        let mut expected_index = 1;
+        let mut i = 0;
+
+        while i < self.lines.len() {
+            if self.is_blank(i) {
+                i += 1;
+                continue;
+            }
+
+            // 1. 番号行
+            let timing_index = if self.is_index(i) {
+                let index: usize = self.lines[i].trim().parse().unwrap_or(0);
+                if index != expected_index {
+                    self.report(i, Severity::Warning, format!("cue index {} is out of sequence (expected {})", index, expected_index));
+                }
+                expected_index = index + 1;
+                i + 1
+            } else if self.is_timing(i) || self.lines[i].contains("-->") {
+                self.report(i, Severity::Warning, format!("missing cue index before timing line (expected {})", expected_index));
+                expected_index += 1;
+                i
+            } else {
+                // キューの外にあるテキスト。多くは本文中の空行なので直前のキューに続ける
+                let text = self.lines[i].trim().to_string();
+                self.report(i, Severity::Error, format!("text outside of any cue: '{}'", text));
+                if let Some(previous) = cues.last_mut() {
+                    previous.text.push('\n');
+                    previous.text.push_str(&text);
+                }
+                i += 1;
+                continue;
+            };
+
+            // 2. タイミング行
+            if self.is_blank(timing_index) {
+                self.report(i, Severity::Error, "cue has no timing line".to_string());
+                i = timing_index;
+                continue;
+            }
+            let Some((start, mut end)) = self.parse_timing(timing_index) else {
+                let line = self.lines[timing_index].trim().to_string();
+                self.report(timing_index, Severity::Error, format!("malformed timing line, cue skipped: '{}'", line));
+                i = timing_index + 1;
+                while !self.is_blank(i) {
+                    i += 1;
+                }
+                continue;
+            };
+
+            // 3. 本文（次の空行まで。空行を挟まずに次のキューが始まる場合はそこで区切る）
+            let mut text_lines = Vec::new();
+            let mut j = timing_index + 1;
+            while !self.is_blank(j) {
+                if (self.is_index(j) && self.is_timing(j + 1)) || self.is_timing(j) {
+                    self.report(j, Severity::Warning, "missing blank line before the next cue".to_string());
+                    break;
+                }
+                text_lines.push(self.lines[j].trim());
+                j += 1;
+            }
+            i = j;
+
+            if text_lines.is_empty() {
+                self.report(timing_index, Severity::Warning, "empty cue, skipped".to_string());
+                continue;
+            }
+            if end < start {
+                self.report(timing_index, Severity::Error, format!("end time {} is before start time {}", end, start));
+                end = start;
+            }
+            if let Some(previous) = cues.last() {
+                if start < previous.start {
+                    self.report(timing_index, Severity::Warning, format!("cue starts at {}, before the previous cue ({})", start, previous.start));
+                } else if start < previous.end {
+                    self.report(timing_index, Severity::Warning, format!("cue overlaps the previous cue by {} ms", (previous.end - start).as_millis()));
+                }
+            }
+
+            cues.push(Subtitle {
+                start,
+                end,
+                text: text_lines.join("\n"),
+                speaker: None,
+            });
+        }
+
+        // 時刻が前後している場合でも、文の結合は時刻順に行う
+        cues.sort_by_key(|cue| cue.start);
+        (cues, self.diagnostics)
+    }
+}
+
+// SRTを1行ずつ読み、問題を行番号付きで報告しながら、読み取れたキューを返す
+pub fn parse(content: &str) -> (Vec<Subtitle>, Vec<Diagnostic>) {
+    let tokenizer = Tokenizer {
+        lines: content.trim_start_matches('\u{feff}').lines().collect(),
+        standard_timing_re: Regex::new(r"^(\d{2}:\d{2}:\d{2},\d{3}) --> (\d{2}:\d{2}:\d{2},\d{3})(?:\s+.*)?$").unwrap(),
+        loose_timing_re: Regex::new(r"^\s*(\d{1,2}):(\d{1,2}):(\d{1,2})(?:[,.:](\d{1,3}))?\s*-{1,2}>\s*(\d{1,2}):(\d{1,2}):(\d{1,2})(?:[,.:](\d{1,3}))?").unwrap(),
+        diagnostics: Vec::new(),
+    };
+    tokenizer.tokenize()
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+use super::Subtitle;
+use crate::language::Language;
+
+// これより短いテキストは句読点の有無を判断しない
+const MIN_WORDS_FOR_DETECTION: usize = 30;
+// この語数あたり1個未満しか文末記号がなければ句読点のない字幕とみなす
+const WORDS_PER_TERMINATOR_THRESHOLD: usize = 40;
+// 空白で区切らない言語では、この文字数を1語とみなす
+const CHARS_PER_WORD: usize = 2;
+
+// 語数を数える単位。空白で区切らない言語では1文字ずつ（空白は除く）
+fn units<'a>(text: &'a str, language: &Language) -> Vec<&'a str> {
+    if language.without_spaces {
+        text.char_indices().filter(|(_, c)| !c.is_whitespace()).map(|(i, c)| &text[i..i + c.len_utf8()]).collect()
+    } else {
+        text.split_whitespace().collect()
+    }
+}
+
+// 自動生成字幕のように文末記号がほとんどない字幕かどうか
+pub fn is_unpunctuated(cues: &[Subtitle], language: &Language) -> bool {
+    let units_per_word = if language.without_spaces { CHARS_PER_WORD } else { 1 };
+    let words: usize = cues.iter().map(|cue| units(&cue.text, language).len()).sum::<usize>() / units_per_word;
+    if words < MIN_WORDS_FOR_DETECTION {
+        return false;
+    }
+    let terminators: usize = cues
+        .iter()
+        .map(|cue| cue.text.chars().filter(|c| matches!(c, '.' | '?' | '!' | '…' | '。' | '？' | '！')).count())
+        .sum();
+    terminators * WORDS_PER_TERMINATOR_THRESHOLD < words
+}
+
+// 句読点のない字幕を、キュー間の無音区間と最大語数で区切って「文」にする
+pub fn split_by_pauses(cues: &[Subtitle], pause_gap: Duration, max_words: usize, language: &Language) -> Vec<Subtitle> {
+    // 空白で区切らない言語では1文字ずつ数え、CHARS_PER_WORD 文字を1語とする
+    let (max_words, separator) = if language.without_spaces { (max_words.max(1) * CHARS_PER_WORD, "") } else { (max_words.max(1), " ") };
+    let mut sentences: Vec<Subtitle> = Vec::new();
+    let mut current: Option<Subtitle> = None;
+    let mut current_words = 0;
+    let mut previous_end = None;
+
+    for cue in cues {
+        let words = units(&cue.text, language);
+        if words.is_empty() {
+            continue;
+        }
+
+        let paused = previous_end.is_some_and(|end| cue.start.saturating_duration_since(end) >= pause_gap);
+        let speaker_changed = cue.speaker.is_some()
+            && current.as_ref().is_some_and(|sentence| sentence.speaker.is_some() && sentence.speaker != cue.speaker);
+        if (paused || speaker_changed)
+            && let Some(sentence) = current.take()
+        {
+            sentences.push(sentence);
+            current_words = 0;
+        }
+        previous_end = Some(cue.end);
+
+        // できるだけキューの境界で区切る
+        if current_words > 0
+            && current_words + words.len() > max_words
+            && let Some(sentence) = current.take()
+        {
+            sentences.push(sentence);
+            current_words = 0;
+        }
+
+        // 1つのキューが最大語数をまたぐ場合は途中で区切り、後半も同じキューの時刻を使う
+        let mut remaining = words.as_slice();
+        while !remaining.is_empty() {
+            if current_words >= max_words
+                && let Some(sentence) = current.take()
+            {
+                sentences.push(sentence);
+                current_words = 0;
+            }
+            let take = (max_words - current_words).min(remaining.len());
+            let text = remaining[..take].join(separator);
+            match current.as_mut() {
+                Some(sentence) => {
+                    sentence.text.push_str(separator);
+                    sentence.text.push_str(&text);
+                    sentence.end = cue.end;
+                }
+                None => {
+                    current = Some(Subtitle {
+                        start: cue.start,
+                        end: cue.end,
+                        text,
+                        speaker: cue.speaker.clone(),
+                    });
+                }
+            }
+            current_words += take;
+            remaining = &remaining[take..];
+        }
+
+        // 句読点が一部だけ付いている場合は、それも区切りとして使う
+        if cue.text.ends_with(['.', '?', '!', '。', '？', '！'])
+            && let Some(sentence) = current.take()
+        {
+            sentences.push(sentence);
+            current_words = 0;
+        }
+    }
+
+    if let Some(sentence) = current {
+        sentences.push(sentence);
+    }
+
+    sentences
+}
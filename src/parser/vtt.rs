@@ -0,0 +1,88 @@
+use regex::Regex;
+use super::Subtitle;
+use crate::timestamp::Timestamp;
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&lrm;", "")
+        .replace("&rlm;", "")
+        .replace("&amp;", "&")
+}
+
+// 空白だけの行でブロックに区切る。WebVTTの仕様と同じく、空行がなくても "-->" を含む行から次のキューが始まる
+fn blocks(content: &str) -> Vec<Vec<&str>> {
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if line.contains("-->") && current.iter().any(|line| line.contains("-->")) {
+            blocks.push(std::mem::take(&mut current));
+        }
+        current.push(line);
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+pub fn parse(content: &str) -> Vec<Subtitle> {
+    // <c>, <v Speaker>, <00:00:01.000> などのキュー内タグは本文として扱わない
+    let tag_re = Regex::new(r"<[^>]*>").unwrap();
+    // 話者は <v Speaker> または <v.class Speaker> で指定される
+    let voice_re = Regex::new(r"<v(?:\.[^ \t>]*)?[ \t]+([^>]+)>").unwrap();
+
+    let normalized = content.trim_start_matches('\u{feff}').replace("\r\n", "\n").replace('\r', "\n");
+    let mut subtitles = Vec::new();
+
+    for (block_index, block) in blocks(&normalized).into_iter().enumerate() {
+        // 先頭のヘッダーブロックとNOTE/STYLE/REGIONブロックは読み飛ばす
+        if block_index == 0 && block[0].starts_with("WEBVTT") {
+            continue;
+        }
+        let first_word = block[0].split_whitespace().next().unwrap_or("");
+        if matches!(first_word, "NOTE" | "STYLE" | "REGION") {
+            continue;
+        }
+
+        let mut lines = block.into_iter();
+        let mut timing_line = lines.next().unwrap_or("");
+        if !timing_line.contains("-->") {
+            // キュー識別子がある場合は次の行がタイミング行
+            timing_line = lines.next().unwrap_or("");
+            if !timing_line.contains("-->") {
+                continue;
+            }
+        }
+
+        // 終了時刻の後ろにはキュー設定（align:start など）が続くことがある
+        let (start, rest) = timing_line.split_once("-->").unwrap();
+        let end = rest.split_whitespace().next().unwrap_or("");
+        let (Ok(start), Ok(end)) = (Timestamp::parse_vtt(start.trim()), Timestamp::parse_vtt(end)) else {
+            continue;
+        };
+
+        let text_lines: Vec<&str> = lines.collect();
+        let speaker = text_lines
+            .iter()
+            .find_map(|line| voice_re.captures(line))
+            .map(|cap| decode_entities(cap[1].trim()));
+        let text = text_lines
+            .iter()
+            .map(|line| decode_entities(&tag_re.replace_all(line, "")).trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        subtitles.push(Subtitle { start, end, text, speaker });
+    }
+
+    subtitles
+}
@@ -0,0 +1,264 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use crate::cache::hash_hex;
+use crate::language::{self, Language};
+
+// テンプレートで使える変数。{{ と }} はそれぞれ { と } になる
+pub const VARIABLES: &[&str] = &[
+    "sentence",
+    "previous",
+    "next",
+    "title",
+    "context",
+    "grammar_tags",
+    "grammar_tags_field",
+    "source_language",
+    "target_language",
+];
+
+// 英語の字幕を日本語で解説するための日本語のプロンプト
+const JAPANESE_TEMPLATES: &[(&str, &str)] = &[
+    ("translation", include_str!("../prompts/ja/translation.txt")),
+    ("explanation", include_str!("../prompts/ja/explanation.txt")),
+    ("vocabulary", include_str!("../prompts/ja/vocabulary.txt")),
+    ("grammar_tags", include_str!("../prompts/ja/grammar_tags.txt")),
+    ("difficulty", include_str!("../prompts/ja/difficulty.txt")),
+    ("structured", include_str!("../prompts/ja/structured.txt")),
+    ("punctuation", include_str!("../prompts/ja/punctuation.txt")),
+];
+
+// それ以外の言語の組み合わせ用。{source_language} と {target_language} で言語を指定する英語のプロンプト
+const GENERIC_TEMPLATES: &[(&str, &str)] = &[
+    ("translation", include_str!("../prompts/generic/translation.txt")),
+    ("explanation", include_str!("../prompts/generic/explanation.txt")),
+    ("vocabulary", include_str!("../prompts/generic/vocabulary.txt")),
+    ("grammar_tags", include_str!("../prompts/generic/grammar_tags.txt")),
+    ("difficulty", include_str!("../prompts/generic/difficulty.txt")),
+    ("structured", include_str!("../prompts/generic/structured.txt")),
+    ("punctuation", include_str!("../prompts/generic/punctuation.txt")),
+];
+
+fn uses_japanese_templates(source: &Language, target: &Language) -> bool {
+    source == language::ENGLISH && target == language::JAPANESE
+}
+
+fn default_templates(source: &Language, target: &Language) -> &'static [(&'static str, &'static str)] {
+    if uses_japanese_templates(source, target) { JAPANESE_TEMPLATES } else { GENERIC_TEMPLATES }
+}
+
+// {context} の見出しと各行のラベル。組み込みのテンプレートと同じ言語で書く
+struct ContextLabels {
+    heading: &'static str,
+    title: &'static str,
+    previous: &'static str,
+    next: &'static str,
+}
+
+const JAPANESE_CONTEXT_LABELS: ContextLabels = ContextLabels {
+    heading: "文脈（参考情報です。この部分は翻訳・解説しないでください）",
+    title: "動画のタイトル",
+    previous: "前の文",
+    next: "次の文",
+};
+
+const GENERIC_CONTEXT_LABELS: ContextLabels = ContextLabels {
+    heading: "Context (for reference only; do not translate or explain this part)",
+    title: "Video title",
+    previous: "Previous sentence",
+    next: "Next sentence",
+};
+
+// 前後の文とタイトルを {context} に入るブロックにまとめる。前後の文もタイトルもなければ空文字列。
+// テンプレートでは {context} の直後に改行を書かずに使えるよう、空でなければ空行で終わる
+pub fn context_block(title: &str, previous: &[&str], next: &[&str], source: &Language, target: &Language) -> String {
+    if previous.is_empty() && next.is_empty() && title.is_empty() {
+        return String::new();
+    }
+    let labels = if uses_japanese_templates(source, target) { &JAPANESE_CONTEXT_LABELS } else { &GENERIC_CONTEXT_LABELS };
+    let mut block = format!("{}:\n", labels.heading);
+    if !title.is_empty() {
+        block.push_str(&format!("{}: \"{}\"\n", labels.title, title));
+    }
+    for sentence in previous {
+        block.push_str(&format!("{}: \"{}\"\n", labels.previous, sentence));
+    }
+    for sentence in next {
+        block.push_str(&format!("{}: \"{}\"\n", labels.next, sentence));
+    }
+    block.push('\n');
+    block
+}
+
+// 構造化出力（structured）の grammar_tags の項目の説明。組み込みのテンプレートと同じ言語で書く
+const JAPANESE_GRAMMAR_TAGS_FIELD: &str = "- grammar_tags: 次の一覧のうち、この文で使われている文法事項のIDの配列。一覧にないIDは使わず、該当するものがなければ空の配列";
+const GENERIC_GRAMMAR_TAGS_FIELD: &str = "- grammar_tags: an array of the IDs of the grammar points from the list below that are used in this sentence; do not use IDs that are not in the list, and return an empty array if none apply";
+
+// 文法事項の一覧（GrammarTaxonomy::prompt_list）を、{grammar_tags_field} に入る項目の説明にまとめる。
+// タグ付けしないときは呼ばずに空文字列にする。テンプレートで {grammar_tags_field} の直後に空行を書かずに使えるよう、改行で終わる
+pub fn grammar_tags_field(list: &str, source: &Language, target: &Language) -> String {
+    let description = if uses_japanese_templates(source, target) { JAPANESE_GRAMMAR_TAGS_FIELD } else { GENERIC_GRAMMAR_TAGS_FIELD };
+    format!("{}\n{}\n", description, list)
+}
+
+pub struct PromptVariables<'a> {
+    pub sentence: &'a str,
+    // 前後の文。先頭・末尾の文では空文字列
+    pub previous: &'a str,
+    pub next: &'a str,
+    pub title: &'a str,
+    // context_block で作った前後の文とタイトル。--context 0 では空文字列
+    pub context: &'a str,
+    // タグ付けに使う文法事項の一覧（GrammarTaxonomy::prompt_list）。一覧がなければ空文字列
+    pub grammar_tags: &'a str,
+    // grammar_tags_field で作った構造化出力の grammar_tags の項目。タグ付けしなければ空文字列
+    pub grammar_tags_field: &'a str,
+    pub source_language: &'a str,
+    pub target_language: &'a str,
+}
+
+impl PromptVariables<'_> {
+    fn get(&self, name: &str) -> &str {
+        match name {
+            "sentence" => self.sentence,
+            "previous" => self.previous,
+            "next" => self.next,
+            "title" => self.title,
+            "context" => self.context,
+            "grammar_tags" => self.grammar_tags,
+            "grammar_tags_field" => self.grammar_tags_field,
+            "source_language" => self.source_language,
+            "target_language" => self.target_language,
+            _ => unreachable!("unknown prompt variable {}", name),
+        }
+    }
+}
+
+enum Segment {
+    Literal(String),
+    Variable(&'static str),
+}
+
+pub struct PromptTemplate {
+    segments: Vec<Segment>,
+    hash: String,
+}
+
+impl PromptTemplate {
+    pub fn parse(name: &str, text: &str) -> io::Result<Self> {
+        let text = text.trim_end();
+        let invalid = |message: String| io::Error::new(ErrorKind::InvalidData, format!("prompt template '{}': {}", name, message));
+
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(position) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..position]);
+            let tail = &rest[position..];
+            if tail.starts_with("{{") || tail.starts_with("}}") {
+                literal.push_str(&tail[..1]);
+                rest = &tail[2..];
+            } else if tail.starts_with('}') {
+                return Err(invalid("unmatched '}' (write '}}' for a literal brace)".to_string()));
+            } else {
+                let end = tail.find('}').ok_or_else(|| invalid("unclosed '{' (write '{{' for a literal brace)".to_string()))?;
+                let variable = tail[1..end].trim();
+                let variable = VARIABLES.iter().find(|known| **known == variable).ok_or_else(|| {
+                    invalid(format!("unknown variable {{{}}} (available: {})", variable, VARIABLES.join(", ")))
+                })?;
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                segments.push(Segment::Variable(variable));
+                rest = &tail[end + 1..];
+            }
+        }
+        literal.push_str(rest);
+        segments.push(Segment::Literal(literal));
+
+        if !segments.iter().any(|segment| matches!(segment, Segment::Variable("sentence"))) {
+            return Err(invalid("the template must contain {sentence}".to_string()));
+        }
+        Ok(PromptTemplate { segments, hash: hash_hex(text) })
+    }
+
+    // テンプレート本文のSHA-256。analysis.jsonl に記録し、どのプロンプトで解析したかを区別する
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn render(&self, variables: &PromptVariables) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.as_str(),
+                Segment::Variable(name) => variables.get(name),
+            })
+            .collect()
+    }
+
+    // {sentence} 以外に使っている変数の値。同じ文でも前後の文やタイトルが違えば別の応答になるので、キャッシュのキーに含める
+    pub fn context_values<'a>(&self, variables: &'a PromptVariables) -> Vec<&'a str> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Variable(name) if *name != "sentence" => Some(variables.get(name)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+pub struct PromptTemplates {
+    pub translation: PromptTemplate,
+    pub explanation: PromptTemplate,
+    pub vocabulary: PromptTemplate,
+    pub grammar_tags: PromptTemplate,
+    pub difficulty: PromptTemplate,
+    pub structured: PromptTemplate,
+    pub punctuation: PromptTemplate,
+}
+
+impl PromptTemplates {
+    // dir に <名前>.txt があればそれを、なければ言語の組み合わせに合った組み込みのテンプレートを使う
+    pub fn load(dir: Option<&Path>, source: &Language, target: &Language) -> io::Result<Self> {
+        let defaults = default_templates(source, target);
+        let load = |name: &str| -> io::Result<PromptTemplate> {
+            if let Some(dir) = dir {
+                let path = dir.join(format!("{}.txt", name));
+                match fs::read_to_string(&path) {
+                    Ok(text) => {
+                        println!("Using prompt template {}", path.display());
+                        return PromptTemplate::parse(&path.display().to_string(), &text);
+                    }
+                    Err(e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => return Err(io::Error::new(e.kind(), format!("Cannot read {}: {}", path.display(), e))),
+                }
+            }
+            let (_, text) = defaults.iter().find(|(default_name, _)| *default_name == name).unwrap();
+            PromptTemplate::parse(name, text)
+        };
+        Ok(PromptTemplates {
+            translation: load("translation")?,
+            explanation: load("explanation")?,
+            vocabulary: load("vocabulary")?,
+            grammar_tags: load("grammar_tags")?,
+            difficulty: load("difficulty")?,
+            structured: load("structured")?,
+            punctuation: load("punctuation")?,
+        })
+    }
+}
+
+// 組み込みのテンプレートを編集用に書き出す。既存のファイルは上書きしない
+pub fn write_default_templates(dir: &Path, source: &Language, target: &Language) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, text) in default_templates(source, target) {
+        let path = dir.join(format!("{}.txt", name));
+        if path.exists() {
+            println!("Skipped {} (already exists)", path.display());
+            continue;
+        }
+        fs::write(&path, text)?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+use std::time::Duration;
+
+// 字幕のタイムスタンプ（ミリ秒精度）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp {
+    millis: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTimestampError {
+    input: String,
+}
+
+impl fmt::Display for ParseTimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid timestamp: '{}'", self.input)
+    }
+}
+
+impl std::error::Error for ParseTimestampError {}
+
+impl Timestamp {
+    pub const ZERO: Timestamp = Timestamp { millis: 0 };
+
+    pub fn from_millis(millis: u64) -> Self {
+        Timestamp { millis }
+    }
+
+    // 時・分・秒・ミリ秒から組み立てる。u64 に収まらなければ None
+    pub fn from_hms_millis(h: u64, m: u64, s: u64, millis: u64) -> Option<Self> {
+        let secs = h.checked_mul(3600)?.checked_add(m.checked_mul(60)?)?.checked_add(s)?;
+        Some(Timestamp::from_millis(secs.checked_mul(1000)?.checked_add(millis)?))
+    }
+
+    pub fn as_millis(self) -> u64 {
+        self.millis
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.millis as f64 / 1000.0
+    }
+
+    // "HH:MM:SS,mmm"（時間は省略可）と "HH:MM:SS.mmm" の両方を受け付け、区切り文字で分岐する
+    fn parse_with_separator(input: &str, separator: char) -> Result<Self, ParseTimestampError> {
+        let error = || ParseTimestampError { input: input.to_string() };
+        let (clock, fraction) = input.trim().split_once(separator).ok_or_else(error)?;
+        if fraction.is_empty() || fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(error());
+        }
+        // 桁数が3未満の小数部は右側をゼロで埋めて解釈する（".5" は 500ms）
+        let millis: u64 = format!("{:0<3}", fraction).parse().map_err(|_| error())?;
+
+        let parts = clock
+            .split(':')
+            .map(|part| {
+                if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                    Err(error())
+                } else {
+                    part.parse::<u64>().map_err(|_| error())
+                }
+            })
+            .collect::<Result<Vec<u64>, _>>()?;
+        let (h, m, s) = match parts.as_slice() {
+            [h, m, s] => (*h, *m, *s),
+            [m, s] => (0, *m, *s),
+            _ => return Err(error()),
+        };
+        if m >= 60 || s >= 60 {
+            return Err(error());
+        }
+
+        Timestamp::from_hms_millis(h, m, s, millis).ok_or_else(error)
+    }
+
+    pub fn parse_srt(input: &str) -> Result<Self, ParseTimestampError> {
+        Self::parse_with_separator(input, ',')
+    }
+
+    pub fn parse_vtt(input: &str) -> Result<Self, ParseTimestampError> {
+        Self::parse_with_separator(input, '.')
+    }
+
+    fn components(self) -> (u64, u64, u64, u64) {
+        let total_secs = self.millis / 1000;
+        (total_secs / 3600, (total_secs / 60) % 60, total_secs % 60, self.millis % 1000)
+    }
+
+    pub fn to_srt_string(self) -> String {
+        let (h, m, s, ms) = self.components();
+        format!("{:02}:{:02}:{:02},{:03}", h, m, s, ms)
+    }
+
+    pub fn to_vtt_string(self) -> String {
+        let (h, m, s, ms) = self.components();
+        format!("{:02}:{:02}:{:02}.{:03}", h, m, s, ms)
+    }
+
+    // 引数の方が後の時刻なら 0 を返す
+    pub fn saturating_duration_since(self, earlier: Timestamp) -> Duration {
+        Duration::from_millis(self.millis.saturating_sub(earlier.millis))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_srt_string())
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = ParseTimestampError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if input.contains(',') {
+            Self::parse_srt(input)
+        } else {
+            Self::parse_vtt(input)
+        }
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, rhs: Duration) -> Timestamp {
+        Timestamp::from_millis(self.millis + rhs.as_millis() as u64)
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, rhs: Duration) -> Timestamp {
+        Timestamp::from_millis(self.millis.saturating_sub(rhs.as_millis() as u64))
+    }
+}
+
+impl Sub<Timestamp> for Timestamp {
+    type Output = Duration;
+
+    fn sub(self, rhs: Timestamp) -> Duration {
+        self.saturating_duration_since(rhs)
+    }
+}
+
+// JSONでは読みやすさと既存ファイルとの互換性のためにSRT形式の文字列として保存する
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_srt_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
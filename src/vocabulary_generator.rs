@@ -0,0 +1,83 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use crate::analyzer::{read_analysis_jsonl, CefrLevel};
+use crate::timestamp::Timestamp;
+
+#[derive(Serialize)]
+struct VocabularyEntry {
+    lemma: String,
+    part_of_speech: String,
+    // 文ごとの推定が分かれたときは最も多いもの（同数なら低い方）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cefr: Option<CefrLevel>,
+    // 文中に現れた形と意味（重複なし、出現順）
+    surfaces: Vec<String>,
+    glosses: Vec<String>,
+    occurrences: Vec<Occurrence>,
+}
+
+#[derive(Serialize)]
+struct Occurrence {
+    start: Timestamp,
+    sentence: String,
+}
+
+// analysis.jsonl の各文の語彙を辞書形と品詞ごとにまとめ、vocabulary.json に書き出す。
+// CEFRレベルの低い順（レベル不明は最後）、同じレベルでは辞書形の順に並べる
+pub fn generate_vocabulary_from_jsonl(jsonl_path: &Path, output_dir: &Path) -> io::Result<()> {
+    let results = read_analysis_jsonl(jsonl_path)?;
+
+    let mut entries: Vec<VocabularyEntry> = Vec::new();
+    let mut levels: Vec<HashMap<CefrLevel, usize>> = Vec::new();
+    let mut index_by_key: HashMap<(String, String), usize> = HashMap::new();
+    for result in &results {
+        for item in &result.vocabulary {
+            // 以前の analysis.jsonl には辞書形がない
+            let lemma = if item.lemma.trim().is_empty() { item.surface.trim() } else { item.lemma.trim() };
+            let key = (lemma.to_lowercase(), item.part_of_speech.trim().to_lowercase());
+            let index = *index_by_key.entry(key).or_insert_with(|| {
+                entries.push(VocabularyEntry {
+                    lemma: lemma.to_string(),
+                    part_of_speech: item.part_of_speech.trim().to_string(),
+                    cefr: None,
+                    surfaces: Vec::new(),
+                    glosses: Vec::new(),
+                    occurrences: Vec::new(),
+                });
+                levels.push(HashMap::new());
+                entries.len() - 1
+            });
+
+            let entry = &mut entries[index];
+            let surface = item.surface.trim();
+            if !entry.surfaces.iter().any(|s| s == surface) {
+                entry.surfaces.push(surface.to_string());
+            }
+            let gloss = item.gloss.trim();
+            if !gloss.is_empty() && !entry.glosses.iter().any(|g| g == gloss) {
+                entry.glosses.push(gloss.to_string());
+            }
+            // 同じ文に同じ語が2回挙がっていても出現は1回と数える
+            if entry.occurrences.last().is_none_or(|o| o.start != result.start || o.sentence != result.original_sentence) {
+                entry.occurrences.push(Occurrence { start: result.start, sentence: result.original_sentence.clone() });
+            }
+            if let Some(level) = item.cefr {
+                *levels[index].entry(level).or_default() += 1;
+            }
+        }
+    }
+
+    for (entry, counts) in entries.iter_mut().zip(&levels) {
+        entry.cefr = counts.iter().max_by_key(|(level, count)| (**count, std::cmp::Reverse(**level))).map(|(level, _)| *level);
+    }
+    entries.sort_by_cached_key(|entry| (entry.cefr.is_none(), entry.cefr, entry.lemma.to_lowercase()));
+
+    let output_path = output_dir.join("vocabulary.json");
+    fs::write(&output_path, serde_json::to_string_pretty(&entries)?)?;
+
+    println!("Successfully created vocabulary file at {} ({} words)", output_path.display(), entries.len());
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use crate::language::Language;
 
 async fn get_available_subtitles(url: &str) -> io::Result<Vec<String>> {
     let output = Command::new("yt-dlp").arg("--list-subs").arg(url).output()?;
@@ -8,10 +9,7 @@ async fn get_available_subtitles(url: &str) -> io::Result<Vec<String>> {
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         eprintln!("Failed to list subtitles. Stderr: {}", stderr);
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to list subtitles: {}", stderr),
-        ));
+        return Err(io::Error::other(format!("Failed to list subtitles: {}", stderr)));
     }
 
     let stdout = String::from_utf8_lossy(&output.stdout);
@@ -39,28 +37,30 @@ async fn get_available_subtitles(url: &str) -> io::Result<Vec<String>> {
     Ok(subs)
 }
 
-pub async fn download_youtube_subtitles(url: &str, output_dir: &Path) -> io::Result<PathBuf> {
+// 戻り値の bool は自動生成字幕（ローリング形式）をダウンロードしたかどうか
+pub async fn download_youtube_subtitles(url: &str, output_dir: &Path, language: &Language) -> io::Result<(PathBuf, bool)> {
     println!("Attempting to download subtitles from YouTube: {}", url);
 
     let output_template = "subtitle";
-    let base_lang = "en";
+    let base_lang = language.code;
+    let regional_prefix = format!("{}-", base_lang);
 
-    // 1. Check for available manual English subtitles
-    println!("Checking for available manual English subtitles...");
+    // 1. Check for available manual subtitles in the source language
+    println!("Checking for available manual {} subtitles...", language.name);
     let mut lang_to_try = base_lang.to_string();
     let mut is_manual_sub_found = false;
 
     match get_available_subtitles(url).await {
         Ok(langs) => {
             let best_lang = langs.iter().find(|l| l.as_str() == base_lang)
-                               .or_else(|| langs.iter().find(|l| l.starts_with("en-")));
+                               .or_else(|| langs.iter().find(|l| l.starts_with(&regional_prefix)));
 
             if let Some(lang) = best_lang {
-                println!("Found manual English subtitle: {}", lang);
+                println!("Found manual {} subtitle: {}", language.name, lang);
                 lang_to_try = lang.clone();
                 is_manual_sub_found = true;
             } else {
-                println!("No manual English subtitles found. Will try for automatic captions.");
+                println!("No manual {} subtitles found. Will try for automatic captions.", language.name);
             }
         }
         Err(e) => {
@@ -89,7 +89,7 @@ pub async fn download_youtube_subtitles(url: &str, output_dir: &Path) -> io::Res
 
         if output.status.success() && manual_srt_path.exists() {
             println!("Successfully downloaded manual subtitles to: {}", manual_srt_path.display());
-            return Ok(manual_srt_path);
+            return Ok((manual_srt_path, false));
         }
         println!("Manual subtitles download failed. Falling back to automatic.");
     }
@@ -114,12 +114,12 @@ pub async fn download_youtube_subtitles(url: &str, output_dir: &Path) -> io::Res
     if !output_auto.status.success() || !auto_srt_path.exists() {
         let stderr = String::from_utf8_lossy(&output_auto.stderr);
         eprintln!("yt-dlp failed to download any subtitles.\nStderr: {}", stderr);
-        return Err(io::Error::new(io::ErrorKind::Other, format!("yt-dlp failed to download any subtitles. Stderr: {}", stderr)));
+        return Err(io::Error::other(format!("yt-dlp failed to download any subtitles. Stderr: {}", stderr)));
     }
 
     println!("Successfully downloaded automatic subtitles to: {}", auto_srt_path.display());
 
-    Ok(auto_srt_path)
+    Ok((auto_srt_path, true))
 }
 
 
@@ -131,7 +131,7 @@ pub async fn get_youtube_video_title(url: &str) -> io::Result<String> {
 
     if !output.status.success() {
         eprintln!("yt-dlp failed to get title:\nStdout: {}\nStderr: {}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
-        return Err(io::Error::new(io::ErrorKind::Other, "yt-dlp failed to get video title."));
+        return Err(io::Error::other("yt-dlp failed to get video title."));
     }
 
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
@@ -145,7 +145,7 @@ pub async fn get_youtube_video_duration(url: &str) -> io::Result<String> {
 
     if !output.status.success() {
         eprintln!("yt-dlp failed to get duration:\nStdout: {}\nStderr: {}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
-        return Err(io::Error::new(io::ErrorKind::Other, "yt-dlp failed to get video duration."));
+        return Err(io::Error::other("yt-dlp failed to get video duration."));
     }
 
     Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
@@ -163,7 +163,7 @@ pub async fn download_youtube_thumbnail(url: &str, output_dir: &Path) -> io::Res
 
     if !output.status.success() {
         eprintln!("yt-dlp failed to download thumbnail:\nStdout: {}\nStderr: {}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
-        return Err(io::Error::new(io::ErrorKind::Other, "yt-dlp failed to download thumbnail."));
+        return Err(io::Error::other("yt-dlp failed to download thumbnail."));
     }
 
     let thumbnail_path = find_thumbnail_file(output_dir)?;
@@ -184,14 +184,12 @@ fn find_thumbnail_file(dir: &Path) -> io::Result<PathBuf> {
     for entry in std::fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        if let Some(stem) = path.file_stem() {
-            if stem == "thumbnail" {
-                if let Some(ext) = path.extension() {
-                    if ext == "webp" || ext == "jpg" || ext == "png" || ext == "jpeg" {
-                         return Ok(path);
-                    }
-                }
-            }
+        if let Some(stem) = path.file_stem()
+            && stem == "thumbnail"
+            && let Some(ext) = path.extension()
+            && (ext == "webp" || ext == "jpg" || ext == "png" || ext == "jpeg")
+        {
+            return Ok(path);
         }
     }
     Err(io::Error::new(io::ErrorKind::NotFound, "Thumbnail file not found after download."))
@@ -0,0 +1,278 @@
+// SRTファイルから index.html までの全体の流れを、LLMサーバーなしで確かめる
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::thread;
+
+const SRT: &str = "1\n00:00:01,000 --> 00:00:03,000\nI think we should go home now.\n\n2\n00:00:03,500 --> 00:00:06,000\nShe has been waiting for hours.\n";
+
+fn structured_response(translation: &str) -> String {
+    json!({
+        "translation": translation,
+        "explanation": "**解説**",
+        "vocabulary": [{ "surface": "waiting", "lemma": "wait", "part_of_speech": "verb", "cefr": "A2", "gloss": "待つ" }],
+        "grammar_points": [],
+        "grammar_tags": ["present_perfect_continuous"],
+    })
+    .to_string()
+}
+
+// dir で srtgram を実行する。キャッシュは dir の中に作る
+fn run_srtgram(dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_srtgram"))
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "srtgram failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+fn read_analysis(path: &Path) -> Vec<Value> {
+    fs::read_to_string(path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+// どのリクエストにも同じ応答を返す Ollama の /api/generate の代わり
+fn start_fake_ollama(response: String) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let generated = json!({ "response": response, "eval_count": 10, "eval_duration": 1_000_000 }).to_string();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                // 接続を使い回すリクエストにも答える
+                loop {
+                    let mut content_length = 0;
+                    let mut request_line = String::new();
+                    let mut line = String::new();
+                    loop {
+                        line.clear();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        if request_line.is_empty() {
+                            request_line = line.clone();
+                        }
+                        if line == "\r\n" {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':')
+                            && name.eq_ignore_ascii_case("content-length")
+                        {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                    let mut request = vec![0; content_length];
+                    reader.read_exact(&mut request).unwrap();
+                    // 解析の前に確かめるモデルの一覧にはデフォルトのモデルだけを載せる
+                    let body = if request_line.starts_with("GET /api/tags") {
+                        json!({ "models": [{ "name": "gemma3:12b" }] }).to_string()
+                    } else {
+                        generated.clone()
+                    };
+                    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+                }
+            });
+        }
+    });
+    address
+}
+
+#[test]
+fn replays_canned_responses_from_srt_to_html() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("captions.srt"), SRT).unwrap();
+    let fixture = [
+        json!({ "prompt_contains": "Sentence: \"I think we should go home now.\"", "response": structured_response("もう家に帰るべきだと思う。") }),
+        json!({ "prompt_contains": "Sentence: \"She has been waiting for hours.\"", "response": structured_response("彼女は何時間も待っている。") }),
+    ];
+    fs::write(dir.path().join("fixture.jsonl"), fixture.map(|entry| entry.to_string()).join("\n")).unwrap();
+
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--structured", "--no-cache"]);
+
+    let output_dir = dir.path().join("captions");
+    let results = read_analysis(&output_dir.join("analysis.jsonl"));
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result["status"] == "ok"));
+    assert_eq!(results[1]["translation"], "彼女は何時間も待っている。");
+    assert_eq!(results[1]["grammar_tags"], json!(["present_perfect_continuous"]));
+
+    let html = fs::read_to_string(output_dir.join("index.html")).unwrap();
+    assert!(html.contains("もう家に帰るべきだと思う。"));
+    assert!(html.contains("<strong>解説</strong>"));
+    assert!(html.contains(r#"data-tag="present_perfect_continuous">現在完了進行形</span>"#));
+
+    let vocabulary: Value = serde_json::from_str(&fs::read_to_string(output_dir.join("vocabulary.json")).unwrap()).unwrap();
+    assert_eq!(vocabulary[0]["lemma"], "wait");
+    assert_eq!(vocabulary[0]["occurrences"].as_array().unwrap().len(), 2);
+
+    let metadata: Value = serde_json::from_str(&fs::read_to_string(output_dir.join("metadata.json")).unwrap()).unwrap();
+    assert_eq!(metadata["sentence_count"], 2);
+    assert_eq!(metadata["difficulty"]["sentences"], 2);
+}
+
+#[test]
+fn marks_sentences_without_a_recorded_response_as_failed() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("captions.srt"), SRT).unwrap();
+    let fixture = json!({ "prompt_contains": "I think we should go home now.", "response": structured_response("もう家に帰るべきだと思う。") });
+    fs::write(dir.path().join("fixture.jsonl"), fixture.to_string()).unwrap();
+
+    let output = run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--structured", "--no-cache"]);
+
+    let results = read_analysis(&dir.path().join("captions/analysis.jsonl"));
+    assert_eq!(results[0]["status"], "ok");
+    assert_eq!(results[1]["status"], "failed");
+    assert!(results[1]["error"].as_str().unwrap().contains("no recorded response"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 sentence(s) failed"));
+    assert!(fs::read_to_string(dir.path().join("captions/index.html")).unwrap().contains(r#"<div class="entry failed""#));
+}
+
+#[test]
+fn records_responses_and_replays_them_without_a_server() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("captions.srt"), SRT).unwrap();
+    let host = start_fake_ollama(structured_response("訳"));
+
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--ollama-host", &host, "--structured", "--record", "fixture.jsonl"]);
+    let recorded = fs::read_to_string(dir.path().join("fixture.jsonl")).unwrap();
+    assert_eq!(recorded.lines().count(), 2);
+
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--structured", "--no-cache"]);
+
+    // 2回目の出力先は captions_02
+    let recorded_run = read_analysis(&dir.path().join("captions/analysis.jsonl"));
+    let replayed_run = read_analysis(&dir.path().join("captions_02/analysis.jsonl"));
+    assert_eq!(recorded_run, replayed_run);
+    assert!(replayed_run.iter().all(|result| result["status"] == "ok" && result["translation"] == "訳"));
+}
+
+#[test]
+fn cache_clear_removes_only_cache_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("captions.srt"), SRT).unwrap();
+    let fixture = json!({ "prompt_contains": "Sentence:", "response": structured_response("訳") });
+    fs::write(dir.path().join("fixture.jsonl"), fixture.to_string()).unwrap();
+    let cache_dir = dir.path().join("cache/srtgram");
+    fs::create_dir_all(cache_dir.join("ab")).unwrap();
+    fs::write(cache_dir.join("important.txt"), "keep me").unwrap();
+    fs::write(cache_dir.join("ab/notes.json"), "{}").unwrap();
+
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--structured"]);
+    let output = run_srtgram(dir.path(), &["cache", "--cache-dir", cache_dir.to_str().unwrap(), "clear"]);
+
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Removed 2 entries"));
+    assert!(cache_dir.join("important.txt").exists());
+    assert!(cache_dir.join("ab/notes.json").exists());
+    let shards: Vec<_> = fs::read_dir(&cache_dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+    assert_eq!(shards.len(), 2, "empty shard directories should be removed: {:?}", shards);
+}
+
+#[test]
+fn keeps_sentences_ok_when_optional_requests_fail() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("captions.srt"), SRT).unwrap();
+    let fixture = [
+        json!({ "prompt_contains": "自然な日本語に翻訳してください", "response": "訳" }),
+        json!({ "prompt_contains": "文法的な解説を日本語で提供してください", "response": "**解説**" }),
+    ];
+    fs::write(dir.path().join("fixture.jsonl"), fixture.map(|entry| entry.to_string()).join("\n")).unwrap();
+
+    // 既定では翻訳と解説だけを取得する
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--no-cache"]);
+    let results = read_analysis(&dir.path().join("captions/analysis.jsonl"));
+    assert!(results.iter().all(|result| result["status"] == "ok" && result["translation"] == "訳"));
+    assert!(results.iter().all(|result| result.get("vocabulary").is_none() && result.get("grammar_tags").is_none()));
+
+    // 語彙・文法事項のタグ・難易度の評価を取得できなくても、文は失敗にしない
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--no-cache", "--vocabulary", "--grammar-tags", "--rate-difficulty"]);
+    let results = read_analysis(&dir.path().join("captions_02/analysis.jsonl"));
+    assert!(results.iter().all(|result| result["status"] == "ok" && result["explanation"] == "**解説**"));
+    // タグを取得できなかった文は、節の数を 0 と数えずに難易度を計算する
+    assert!(results.iter().all(|result| result["difficulty"].get("clauses").is_none()));
+    assert!(results.iter().all(|result| result["difficulty"].get("llm_rating").is_none() && result["template_hashes"].get("difficulty").is_none()));
+}
+
+#[test]
+fn restores_punctuation_of_japanese_captions_by_characters() {
+    let dir = tempfile::tempdir().unwrap();
+    let srt = "1\n00:00:01,000 --> 00:00:03,000\n今日はとても良い天気なので公園まで歩いて\n\n\
+               2\n00:00:03,000 --> 00:00:05,000\n行こうと思っていたけれど雨が降ってきたから\n\n\
+               3\n00:00:07,000 --> 00:00:09,000\n家で本を読みながらゆっくり過ごすことにした\n\n\
+               4\n00:00:09,000 --> 00:00:11,000\n明日は晴れるといいなと思いながら寝てしまった\n";
+    fs::write(dir.path().join("captions.srt"), srt).unwrap();
+    // モデルが空白を入れても、元の文字の並びが同じなら使う
+    let punctuated = "今日はとても良い天気なので、公園まで歩いて行こうと思っていたけれど、 雨が降ってきたから、家で本を読みながらゆっくり過ごすことにした。明日は晴れるといいなと思いながら寝てしまった。";
+    let fixture = json!({ "prompt_contains": "subtitle text without punctuation", "response": punctuated });
+    fs::write(dir.path().join("fixture.jsonl"), fixture.to_string()).unwrap();
+
+    let args = ["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--no-cache", "--source-lang", "ja", "--target-lang", "en", "--restore-punctuation"];
+    run_srtgram(dir.path(), &args);
+
+    let sentences: Value = serde_json::from_str(&fs::read_to_string(dir.path().join("captions/sentences.json")).unwrap()).unwrap();
+    let texts: Vec<&str> = sentences.as_array().unwrap().iter().map(|sentence| sentence["text"].as_str().unwrap()).collect();
+    assert_eq!(
+        texts,
+        ["今日はとても良い天気なので、公園まで歩いて行こうと思っていたけれど、雨が降ってきたから、家で本を読みながらゆっくり過ごすことにした。", "明日は晴れるといいなと思いながら寝てしまった。"]
+    );
+    assert_eq!(sentences[1]["start"], "00:00:09,000");
+}
+
+#[test]
+fn resume_with_limit_keeps_results_beyond_the_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let srt = format!("{SRT}\n3\n00:00:07,000 --> 00:00:09,000\nWe can talk tomorrow.\n");
+    fs::write(dir.path().join("captions.srt"), srt).unwrap();
+    let fixture = [
+        json!({ "prompt_contains": "Sentence: \"I think we should go home now.\"", "response": structured_response("もう家に帰るべきだと思う。") }),
+        json!({ "prompt_contains": "Sentence: \"We can talk tomorrow.\"", "response": structured_response("明日話そう。") }),
+    ];
+    fs::write(dir.path().join("fixture.jsonl"), fixture.map(|entry| entry.to_string()).join("\n")).unwrap();
+    let backend = ["--backend", "mock", "--fixture", "fixture.jsonl", "--structured", "--no-cache"];
+
+    run_srtgram(dir.path(), &[&["-l", "captions.srt"][..], &backend].concat());
+    let first_run = read_analysis(&dir.path().join("captions/analysis.jsonl"));
+    assert_eq!(first_run.iter().map(|result| result["status"].as_str().unwrap()).collect::<Vec<_>>(), ["ok", "failed", "ok"]);
+
+    // --limit は解析し直す文を選ぶだけで、範囲外の結果は消さない
+    run_srtgram(dir.path(), &[&["--resume", "captions", "--limit", "1", "--retry-failed"][..], &backend].concat());
+    assert_eq!(read_analysis(&dir.path().join("captions/analysis.jsonl")), first_run);
+}
+
+#[test]
+fn resumes_from_inside_the_output_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("captions.srt"), SRT).unwrap();
+    let fixture = json!({ "prompt_contains": "Sentence:", "response": structured_response("訳") });
+    fs::write(dir.path().join("fixture.jsonl"), fixture.to_string()).unwrap();
+
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--structured", "--no-cache"]);
+    let output_dir = dir.path().join("captions");
+    run_srtgram(&output_dir, &["--resume", ".", "--backend", "mock", "--fixture", "../fixture.jsonl", "--structured", "--no-cache"]);
+
+    let metadata: Value = serde_json::from_str(&fs::read_to_string(output_dir.join("metadata.json")).unwrap()).unwrap();
+    assert_eq!(metadata["output_dir_name"], "captions");
+    assert_eq!(metadata["report_path"], "captions/index.html");
+}
+
+#[test]
+fn leaves_clauses_out_with_a_taxonomy_without_clause_tags() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("captions.srt"), SRT).unwrap();
+    fs::create_dir(dir.path().join("prompts")).unwrap();
+    fs::write(dir.path().join("prompts/grammar_taxonomy.txt"), "phrasal_verb: Phrasal verb\nmodal_should: Should\n").unwrap();
+    let fixture = json!({ "prompt_contains": "Sentence:", "response": structured_response("訳") });
+    fs::write(dir.path().join("fixture.jsonl"), fixture.to_string()).unwrap();
+
+    run_srtgram(dir.path(), &["-l", "captions.srt", "--backend", "mock", "--fixture", "fixture.jsonl", "--structured", "--no-cache", "--prompt-dir", "prompts"]);
+
+    // 独自の一覧には節を作る文法事項がないので、節の数を 0 と数えない
+    let results = read_analysis(&dir.path().join("captions/analysis.jsonl"));
+    assert!(results.iter().all(|result| result["status"] == "ok" && result["difficulty"].get("clauses").is_none()));
+}